@@ -0,0 +1,204 @@
+use dioxus::prelude::*;
+
+use crate::{db::Contact, Route, DISPLAY_UNIT, WALLET};
+
+/// All contacts, with a way to add more.
+#[component]
+pub fn Contacts() -> Element {
+    let contacts = use_resource(move || async move {
+        match WALLET().multimint().await {
+            Ok(mm) => mm.contacts().await,
+            Err(_) => Default::default(),
+        }
+    });
+
+    rsx! {
+        div {
+            class: "screen",
+            h2 { "Contacts" }
+            Link { class: "join-button", to: Route::NewContact {}, "Add contact" }
+            match contacts() {
+                Some(contacts) if contacts.is_empty() => rsx! {
+                    p { class: "screen-hint", "No contacts yet" }
+                },
+                Some(contacts) => rsx! {
+                    div {
+                        class: "contact-list",
+                        for (id, contact) in contacts {
+                            ContactItem { key: "{id}", id, contact }
+                        }
+                    }
+                },
+                None => rsx! {
+                    div { class: "spinner" }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn ContactItem(id: u64, contact: Contact) -> Element {
+    let identifiers = contact.identifiers.join(", ");
+    rsx! {
+        Link {
+            class: "contact-item",
+            to: Route::EditContact { id },
+            ContactAvatar { contact: contact.clone() }
+            div {
+                p { class: "contact-name", "{contact.name}" }
+                p { class: "contact-identifiers", "{identifiers}" }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn NewContact() -> Element {
+    rsx! {
+        ContactForm { id: None, contact: Contact::default() }
+    }
+}
+
+#[component]
+pub fn EditContact(id: u64) -> Element {
+    let contact = use_resource(use_reactive!(|id| async move {
+        match WALLET().multimint().await {
+            Ok(mm) => mm.contact(id).await,
+            Err(_) => None,
+        }
+    }));
+
+    match contact() {
+        Some(Some(contact)) => rsx! {
+            ContactForm { key: "{id}", id: Some(id), contact }
+        },
+        Some(None) => rsx! {
+            div {
+                class: "screen",
+                p { class: "screen-hint", "This contact does not exist." }
+                Link { to: Route::Contacts {}, "All contacts" }
+            }
+        },
+        None => rsx! {
+            div { class: "spinner" }
+        },
+    }
+}
+
+/// The contact's avatar, or its initial if it has none.
+#[component]
+fn ContactAvatar(contact: Contact) -> Element {
+    let initial = contact
+        .name
+        .chars()
+        .next()
+        .map(|initial| initial.to_uppercase().to_string())
+        .unwrap_or_default();
+    match contact.avatar_url {
+        Some(url) => rsx! {
+            img { class: "contact-avatar", src: "{url}", alt: "{contact.name}" }
+        },
+        None => rsx! {
+            span { class: "contact-avatar", "{initial}" }
+        },
+    }
+}
+
+/// Edits `contact`, stored under `id` or under a new id if `id` is `None`.
+#[component]
+fn ContactForm(id: Option<u64>, contact: Contact) -> Element {
+    let mut name = use_signal(|| contact.name.clone());
+    let mut avatar_url = use_signal(|| contact.avatar_url.clone().unwrap_or_default());
+    let mut identifiers = use_signal(|| contact.identifiers.join("\n"));
+    let mut notes = use_signal(|| contact.notes.clone());
+    let mut error = use_signal(|| None::<String>);
+
+    let on_save = move |_| {
+        let contact = Contact {
+            name: name().trim().to_string(),
+            avatar_url: Some(avatar_url().trim().to_string()).filter(|url| !url.is_empty()),
+            identifiers: identifiers()
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            notes: notes().trim().to_string(),
+        };
+        if contact.name.is_empty() {
+            error.set(Some("Enter a name for the contact".to_string()));
+            return;
+        }
+        spawn(async move {
+            match WALLET().multimint().await {
+                Ok(mm) => {
+                    mm.save_contact(id, &contact).await;
+                    navigator().push(Route::Contacts {});
+                }
+                Err(e) => error.set(Some(e.user_message(DISPLAY_UNIT()))),
+            }
+        });
+    };
+
+    let on_delete = move |_| {
+        let Some(id) = id else {
+            return;
+        };
+        spawn(async move {
+            match WALLET().multimint().await {
+                Ok(mm) => {
+                    mm.delete_contact(id).await;
+                    navigator().push(Route::Contacts {});
+                }
+                Err(e) => error.set(Some(e.user_message(DISPLAY_UNIT()))),
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            class: "screen",
+            Link { class: "back-link", to: Route::Contacts {}, "← Contacts" }
+            h2 { if id.is_some() { "Edit contact" } else { "New contact" } }
+            input {
+                class: "input-box",
+                r#type: "text",
+                placeholder: "Name",
+                value: "{name}",
+                oninput: move |evt| name.set(evt.value())
+            }
+            input {
+                class: "input-box",
+                r#type: "url",
+                placeholder: "Avatar URL (optional)",
+                value: "{avatar_url}",
+                oninput: move |evt| avatar_url.set(evt.value())
+            }
+            textarea {
+                class: "invoice-input",
+                rows: 3,
+                placeholder: "Lightning Addresses, LNURLs or nostr pubkeys, one per line",
+                value: "{identifiers}",
+                oninput: move |evt| identifiers.set(evt.value())
+            }
+            textarea {
+                class: "invoice-input",
+                rows: 3,
+                placeholder: "Notes",
+                value: "{notes}",
+                oninput: move |evt| notes.set(evt.value())
+            }
+            div {
+                class: "button-row",
+                button { class: "invoice-button", onclick: on_save, "Save" }
+                if id.is_some() {
+                    button { class: "secondary-button", onclick: on_delete, "Delete" }
+                }
+            }
+            if let Some(msg) = error() {
+                div { class: "toast-error", "{msg}" }
+            }
+        }
+    }
+}
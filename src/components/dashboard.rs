@@ -1,87 +1,377 @@
 use dioxus::prelude::*;
-use fedimint_core::Amount;
+use fedimint_core::{config::FederationId, Amount};
 
 use crate::{
-    components::receive::Receive, components::send::Send, load_multimint, FederationSelector,
+    components::{
+        deposit::Deposit, pending::PendingOperations, qr::QrDisplay, receive::Receive,
+        redeem::Redeem, send::Send, spend::SpendEcash, withdraw::Withdraw,
+    },
+    error::CarbineError,
+    meta::FederationMeta,
+    multimint::FederationStatus,
+    units::{format_amount, format_number},
+    DashboardAction, FederationSelector, Route, DISPLAY_UNIT, FEDERATIONS, FEDERATION_STATUSES,
+    FIAT_RATE, PENDING_OPERATIONS, PRICE_REFRESH_INTERVAL, WALLET,
 };
 
+#[derive(Clone, Copy, PartialEq)]
+enum Modal {
+    Send,
+    Receive,
+    SpendEcash,
+    Redeem,
+    Deposit,
+    Withdraw,
+    Share,
+}
+
+/// Dashboard of a joined federation, found by the id in the address.
+#[component]
+pub fn Federation(id: FederationId) -> Element {
+    let action = use_context::<Signal<Option<DashboardAction>>>();
+    let selector = FEDERATIONS()
+        .map(|federations| federations.into_iter().find(|item| item.federation_id == id));
+
+    match selector {
+        Some(Some(selector)) => rsx! {
+            Dashboard { key: "{id}", federation_info: selector, action }
+        },
+        Some(None) => rsx! {
+            div {
+                class: "screen",
+                p { class: "screen-hint", "You have not joined this federation." }
+                Link { to: Route::Join { invite_code: String::new() }, "Join a federation" }
+            }
+        },
+        None => rsx! {
+            div { class: "spinner" }
+        },
+    }
+}
+
 #[component]
-pub fn Dashboard(federation_info: FederationSelector) -> Element {
+pub fn Dashboard(
+    federation_info: FederationSelector,
+    action: Signal<Option<DashboardAction>>,
+) -> Element {
+    let status = use_memo(move || {
+        FEDERATION_STATUSES()
+            .get(&federation_info.federation_id)
+            .cloned()
+            .unwrap_or(FederationStatus::Loading)
+    });
+
+    // Reloads once the federation becomes usable and whenever an operation
+    // starts or finishes
     let balance = use_resource(move || async move {
-        let multimint = load_multimint().await;
-        let mm = multimint.read().await;
-        if let Some(mm) = mm.as_ref() {
-            mm.balance(&federation_info.federation_id).await
-        } else {
-            Amount::ZERO
+        if !status().is_usable() {
+            return Err(CarbineError::FederationUnavailable(status()));
+        }
+        let _ = PENDING_OPERATIONS.read().len();
+        let mm = WALLET().multimint().await?;
+        mm.balance(&federation_info.federation_id).await
+    });
+
+    // The fresh invite code needs the client, so reload once it is usable.
+    // It is only offered if it differs from the stored one.
+    let invite_codes = use_resource(move || async move {
+        let _ = status().is_usable();
+        let federation_id = federation_info.federation_id;
+        match WALLET().multimint().await {
+            Ok(mm) => {
+                let stored = mm.invite_code(&federation_id).await;
+                let fresh = mm
+                    .fresh_invite_code(&federation_id)
+                    .await
+                    .filter(|fresh| Some(fresh) != stored.as_ref());
+                (stored, fresh)
+            }
+            Err(_) => (None, None),
+        }
+    });
+
+    let mut modal = use_signal(|| None::<Modal>);
+    let mut prefill = use_signal(|| None::<DashboardAction>);
+
+    // Open the flow a pasted payment string was routed to
+    use_effect(move || {
+        if let Some(pending) = action() {
+            action.set(None);
+            modal.set(Some(match pending {
+                DashboardAction::Send(_) => Modal::Send,
+                DashboardAction::Receive(_) => Modal::Receive,
+                DashboardAction::Redeem(_) => Modal::Redeem,
+                DashboardAction::Withdraw { .. } => Modal::Withdraw,
+            }));
+            prefill.set(Some(pending));
         }
     });
 
-    let mut show_receive = use_signal(|| false);
-    let mut show_send = use_signal(|| false);
+    let mut open = move |target: Modal| {
+        prefill.set(None);
+        modal.set(Some(target));
+    };
 
     rsx! {
         div {
             class: "dashboard",
 
-            h3 { "Balance" }
-            match balance() {
-                Some(bal) => rsx! {
-                    p { class: "balance-text", "{bal}" }
-                },
-                None => rsx! {
+            FederationHeader {
+                name: federation_info.federation_name.clone(),
+                meta: federation_info.meta.clone()
+            }
+
+            match status() {
+                FederationStatus::Ready => rsx! {},
+                FederationStatus::Loading => rsx! {
                     div { class: "spinner" }
-                }
+                    p { class: "status-note", "Loading federation..." }
+                },
+                FederationStatus::Degraded(reason) => rsx! {
+                    p { class: "status-note status-degraded", "{reason}" }
+                },
+                FederationStatus::Failed(failure) => rsx! {
+                    p { class: "status-note status-failed", "{failure.reason()}" }
+                },
+                recovering @ FederationStatus::Recovering { .. } => rsx! {
+                    p { class: "status-note status-recovering", "Recovering your funds, this can take a while" }
+                    progress {
+                        class: "recovery-progress",
+                        max: 100,
+                        value: "{recovering.recovery_percent()}"
+                    }
+                },
             }
-            div {
-                class: "button-row",
-                button {
-                    class: "send-button",
-                    onclick: move |_| {
-                        println!("Send clicked");
-                        show_send.set(true);
+
+            if status().is_usable() {
+                h3 { "Balance" }
+                match &*balance.read() {
+                    Some(Ok(bal)) => rsx! {
+                        p { class: "balance-text", "{format_amount(*bal, DISPLAY_UNIT())}" }
+                        if let Some(rate) = FIAT_RATE() {
+                            p { class: "fiat-text", "≈ {rate.format(*bal)}" }
+                            // The price source failed and the cached price is shown
+                            if rate.age() > 2 * PRICE_REFRESH_INTERVAL {
+                                p {
+                                    class: "screen-hint",
+                                    "Price from {rate.age().as_secs() / 60} minutes ago"
+                                }
+                            }
+                        }
                     },
-                    "Send"
-                }
-                button {
-                    class: "receive-button",
-                    onclick: move |_| {
-                        println!("Receive clicked");
-                        show_receive.set(true);
+                    Some(Err(e)) => rsx! {
+                        p { class: "balance-error", "{e.user_message(DISPLAY_UNIT())}" }
                     },
-                    "Receive"
+                    None => rsx! {
+                        div { class: "spinner" }
+                    }
                 }
-            }
-
-            if show_receive() {
                 div {
-                    class: "modal-overlay",
-                    div {
-                        class: "modal-content",
-                        button {
-                            class: "modal-close-button",
-                            onclick: move|_| show_receive.set(false),
-                            "x"
-                         }
-                         Receive { federation_info: federation_info.clone() }
-                     }
-                 }
+                    class: "button-row",
+                    button {
+                        class: "send-button",
+                        onclick: move |_| {
+                            println!("Send clicked");
+                            open(Modal::Send);
+                        },
+                        "Send"
+                    }
+                    button {
+                        class: "receive-button",
+                        onclick: move |_| {
+                            println!("Receive clicked");
+                            open(Modal::Receive);
+                        },
+                        "Receive"
+                    }
+                }
+                div {
+                    class: "button-row secondary-row",
+                    button {
+                        class: "secondary-button",
+                        onclick: move |_| open(Modal::SpendEcash),
+                        "Spend Ecash"
+                    }
+                    button {
+                        class: "secondary-button",
+                        onclick: move |_| open(Modal::Redeem),
+                        "Redeem Ecash"
+                    }
+                }
+                div {
+                    class: "button-row secondary-row",
+                    button {
+                        class: "secondary-button",
+                        disabled: federation_info.meta.onchain_deposits_disabled,
+                        title: if federation_info.meta.onchain_deposits_disabled { "The guardians disabled on-chain deposits" } else { "" },
+                        onclick: move |_| open(Modal::Deposit),
+                        "Deposit"
+                    }
+                    button {
+                        class: "secondary-button",
+                        onclick: move |_| open(Modal::Withdraw),
+                        "Withdraw"
+                    }
+                    button {
+                        class: "secondary-button",
+                        onclick: move |_| open(Modal::Share),
+                        "Share federation"
+                    }
+                }
+
+                PendingOperations { federation_id: federation_info.federation_id }
+                Link {
+                    class: "history-link",
+                    to: Route::FederationHistory { id: federation_info.federation_id },
+                    "History"
+                }
             }
 
-            if show_send() {
+            if let Some(active) = modal() {
                 div {
                     class: "modal-overlay",
                     div {
                         class: "modal-content",
                         button {
                             class: "modal-close-button",
-                            onclick: move |_| show_send.set(false),
+                            onclick: move |_| {
+                                modal.set(None);
+                                prefill.set(None);
+                            },
                             "x"
                         }
-                        Send { federation_info }
+                        match active {
+                            Modal::Send => rsx! {
+                                Send {
+                                    federation_info: federation_info.clone(),
+                                    initial_input: match prefill() {
+                                        Some(DashboardAction::Send(input)) => input,
+                                        _ => String::new(),
+                                    }
+                                }
+                            },
+                            Modal::Receive => rsx! {
+                                Receive {
+                                    federation_info: federation_info.clone(),
+                                    initial_lnurlw: match prefill() {
+                                        Some(DashboardAction::Receive(lnurlw)) => lnurlw,
+                                        _ => String::new(),
+                                    }
+                                }
+                            },
+                            Modal::SpendEcash => rsx! {
+                                SpendEcash { federation_info: federation_info.clone() }
+                            },
+                            Modal::Redeem => rsx! {
+                                Redeem {
+                                    federation_info: federation_info.clone(),
+                                    initial_notes: match prefill() {
+                                        Some(DashboardAction::Redeem(notes)) => notes,
+                                        _ => String::new(),
+                                    }
+                                }
+                            },
+                            Modal::Deposit => rsx! {
+                                Deposit { federation_info: federation_info.clone() }
+                            },
+                            Modal::Withdraw => rsx! {
+                                Withdraw {
+                                    federation_info: federation_info.clone(),
+                                    initial_address: match prefill() {
+                                        Some(DashboardAction::Withdraw { address, .. }) => address,
+                                        _ => String::new(),
+                                    },
+                                    initial_amount: match prefill() {
+                                        Some(DashboardAction::Withdraw { amount_sats: Some(sats), .. }) => {
+                                            format_number(Amount::from_sats(sats), DISPLAY_UNIT())
+                                        }
+                                        _ => String::new(),
+                                    }
+                                }
+                            },
+                            Modal::Share => rsx! {
+                                div {
+                                    class: "invoice-container",
+                                    h2 { class: "invoice-title", "Share federation" }
+                                    p {
+                                        class: "screen-hint",
+                                        "Anyone with an invite code can join {federation_info.federation_name}."
+                                    }
+                                    match invite_codes() {
+                                        Some((stored, fresh)) => rsx! {
+                                            if let Some(stored) = stored {
+                                                h3 { "Invite code you joined with" }
+                                                QrDisplay { key: "{stored}", value: stored.to_string() }
+                                            } else {
+                                                p { "No invite code stored for this federation" }
+                                            }
+                                            if let Some(fresh) = fresh {
+                                                h3 { "Invite code for the current guardians" }
+                                                p {
+                                                    class: "screen-hint",
+                                                    "Built from the guardian addresses in the federation's latest config, in case the one above is outdated."
+                                                }
+                                                QrDisplay { key: "{fresh}", value: fresh.to_string() }
+                                            }
+                                        },
+                                        None => rsx! {
+                                            div { class: "spinner" }
+                                        }
+                                    }
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Name and icon of the federation, with what its guardians announce.
+#[component]
+fn FederationHeader(name: String, meta: FederationMeta) -> Element {
+    let unit = DISPLAY_UNIT();
+    let mut notes = Vec::new();
+    if let Some(max) = meta.max_balance() {
+        notes.push(format!("Maximum balance {}", format_amount(max, unit)));
+    }
+    if let Some(max) = meta.max_invoice() {
+        notes.push(format!("Maximum invoice {}", format_amount(max, unit)));
+    }
+    if meta.onchain_deposits_disabled {
+        notes.push("On-chain deposits disabled".to_string());
+    }
+    if !meta.vetted_gateways.is_empty() {
+        notes.push(format!("{} vetted gateways", meta.vetted_gateways.len()));
+    }
+    let notes = notes.join(" · ");
+
+    rsx! {
+        div {
+            class: "federation-header",
+            if let Some(url) = &meta.icon_url {
+                img { class: "federation-icon", src: "{url}", alt: "" }
+            }
+            h2 { "{name}" }
+        }
+        if let Some(warning) = meta.shutdown_warning() {
+            div {
+                class: "shutdown-warning",
+                p { "{warning}" }
+                if let Some(successor) = &meta.successor {
+                    Link {
+                        to: Route::Join { invite_code: successor.clone() },
+                        "Join the federation replacing it"
                     }
                 }
             }
         }
+        if let Some(message) = &meta.welcome_message {
+            p { class: "welcome-message", "{message}" }
+        }
+        if !notes.is_empty() {
+            p { class: "screen-hint", "{notes}" }
+        }
     }
 }
@@ -0,0 +1,42 @@
+use dioxus::{logger::tracing::info, prelude::*};
+
+use crate::{components::qr::QrDisplay, FederationSelector, DISPLAY_UNIT, WALLET};
+
+#[component]
+pub fn Deposit(federation_info: FederationSelector) -> Element {
+    let address = use_resource(move || async move {
+        let mm = WALLET()
+            .multimint()
+            .await
+            .map_err(|e| e.user_message(DISPLAY_UNIT()))?;
+        let (_, address) = mm
+            .deposit_address(&federation_info.federation_id)
+            .await
+            .map_err(|e| {
+                info!("Deposit returning error: {e}");
+                e.user_message(DISPLAY_UNIT())
+            })?;
+        WALLET().track_pending(federation_info.federation_id).await;
+        Ok(address.to_string())
+    });
+
+    rsx! {
+        div {
+            class: "invoice-container",
+            h2 { class: "invoice-title", "Deposit On-chain" }
+
+            match address() {
+                Some(Ok(address)) => rsx! {
+                    p { class: "lnurl-range", "Send bitcoin to this address to deposit it into the federation" }
+                    QrDisplay { value: address }
+                },
+                Some(Err(e)) => rsx! {
+                    div { class: "invoice-output", "{e}" }
+                },
+                None => rsx! {
+                    div { class: "spinner" }
+                }
+            }
+        }
+    }
+}
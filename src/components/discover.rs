@@ -0,0 +1,112 @@
+use dioxus::prelude::*;
+
+use crate::{
+    nostr::{discover_federations, DiscoveredFederation},
+    Route, FEDERATIONS, SETTINGS, WALLET,
+};
+
+/// Federations announced on the user's Nostr relays, to join without being
+/// sent an invite code.
+#[component]
+pub fn Discover() -> Element {
+    let mut federations = use_resource(move || async move {
+        let relays = SETTINGS.read().relays();
+        let (contacts, guardian_keys) = match WALLET().multimint().await {
+            Ok(mm) => (mm.contacts().await, mm.guardian_keys().await),
+            Err(_) => Default::default(),
+        };
+        discover_federations(&relays, &contacts, &guardian_keys)
+            .await
+            .map_err(|e| format!("{e:#}"))
+    });
+
+    rsx! {
+        div {
+            class: "screen",
+            h2 { "Discover federations" }
+            p {
+                class: "screen-hint",
+                "Federations announced on Nostr. Anyone can announce one, so prefer those recommended by people you trust."
+            }
+            button {
+                class: "secondary-button",
+                disabled: federations.read().is_none(),
+                onclick: move |_| federations.restart(),
+                "Refresh"
+            }
+            match &*federations.read() {
+                Some(Ok(found)) if found.is_empty() => rsx! {
+                    p { class: "screen-hint", "No federations were announced on your relays." }
+                },
+                Some(Ok(found)) => rsx! {
+                    div {
+                        class: "discover-list",
+                        for federation in found.iter().cloned() {
+                            DiscoveredItem { key: "{federation.federation_id}", federation }
+                        }
+                    }
+                },
+                Some(Err(e)) => rsx! {
+                    p { class: "balance-error", "{e}" }
+                },
+                None => rsx! {
+                    div { class: "spinner" }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn DiscoveredItem(federation: DiscoveredFederation) -> Element {
+    let joined = FEDERATIONS()
+        .unwrap_or_default()
+        .iter()
+        .any(|item| item.federation_id == federation.federation_id);
+    let modules = federation
+        .modules
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    let recommended_by = federation
+        .recommended_by
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    let invite_code = federation.invite_codes.first().cloned().unwrap_or_default();
+
+    rsx! {
+        div {
+            class: "discover-item",
+            div {
+                p { class: "contact-name", "{federation.display_name()}" }
+                if let Some(network) = &federation.network {
+                    span { class: "tag", "{network}" }
+                }
+                if !modules.is_empty() {
+                    p { class: "screen-hint", "Modules: {modules}" }
+                }
+                if !recommended_by.is_empty() {
+                    p { class: "discover-recommended", "Recommended by {recommended_by}" }
+                } else if federation.recommendations > 0 {
+                    p { class: "screen-hint", "{federation.recommendations} recommendations" }
+                }
+            }
+            if joined {
+                Link {
+                    class: "secondary-button",
+                    to: Route::Federation { id: federation.federation_id },
+                    "Joined"
+                }
+            } else {
+                Link {
+                    class: "join-button",
+                    to: Route::Join { invite_code },
+                    "Join"
+                }
+            }
+        }
+    }
+}
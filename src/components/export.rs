@@ -0,0 +1,173 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use dioxus::prelude::*;
+use fedimint_core::config::FederationId;
+
+use crate::{
+    export::{parse_date, ExportFilter, ExportFormat},
+    DISPLAY_UNIT, FEDERATIONS, WALLET,
+};
+
+/// Exports the history of all or some federations for accounting.
+#[component]
+pub fn Export() -> Element {
+    let mut from = use_signal(|| String::new());
+    let mut until = use_signal(|| String::new());
+    let mut format = use_signal(|| ExportFormat::Csv);
+    // Federations left out of the export, so new ones are included by default
+    let mut excluded = use_signal(BTreeSet::<FederationId>::new);
+    let mut exporting = use_signal(|| false);
+    let mut exported = use_signal(|| None::<(ExportFormat, String)>);
+    let mut error = use_signal(|| None::<String>);
+
+    let on_export = move |_| {
+        let filter = match build_filter(&from(), &until(), &excluded()) {
+            Ok(filter) => filter,
+            Err(e) => {
+                error.set(Some(e.to_string()));
+                return;
+            }
+        };
+        let format = format();
+        exporting.set(true);
+        error.set(None);
+        spawn(async move {
+            let export = match WALLET().multimint().await {
+                Ok(mm) => mm.export_history(&filter, format).await,
+                Err(e) => Err(e),
+            };
+            match export {
+                Ok(export) => exported.set(Some((format, export))),
+                Err(e) => error.set(Some(e.user_message(DISPLAY_UNIT()))),
+            }
+            exporting.set(false);
+        });
+    };
+
+    rsx! {
+        div {
+            class: "screen",
+            h2 { "Export history" }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "export-from", "From" }
+                input {
+                    id: "export-from",
+                    class: "settings-select",
+                    r#type: "date",
+                    value: "{from}",
+                    oninput: move |evt| from.set(evt.value())
+                }
+            }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "export-until", "Until" }
+                input {
+                    id: "export-until",
+                    class: "settings-select",
+                    r#type: "date",
+                    value: "{until}",
+                    oninput: move |evt| until.set(evt.value())
+                }
+            }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "export-format", "Format" }
+                select {
+                    id: "export-format",
+                    class: "settings-select",
+                    value: "{format().label()}",
+                    onchange: move |evt| {
+                        if let Some(selected) = ExportFormat::from_label(&evt.value()) {
+                            format.set(selected);
+                        }
+                    },
+                    for choice in ExportFormat::ALL {
+                        option { value: "{choice.label()}", "{choice.label()}" }
+                    }
+                }
+            }
+
+            h3 { "Federations" }
+            for item in FEDERATIONS().unwrap_or_default() {
+                label {
+                    key: "{item.federation_id}",
+                    class: "settings-check",
+                    input {
+                        r#type: "checkbox",
+                        checked: !excluded().contains(&item.federation_id),
+                        onchange: move |evt| {
+                            if evt.checked() {
+                                excluded.write().remove(&item.federation_id);
+                            } else {
+                                excluded.write().insert(item.federation_id);
+                            }
+                        }
+                    }
+                    "{item}"
+                }
+            }
+
+            button {
+                class: "invoice-button",
+                onclick: on_export,
+                disabled: exporting(),
+                if exporting() { "Exporting..." } else { "Export" }
+            }
+            if let Some(msg) = error() {
+                div { class: "toast-error", "{msg}" }
+            }
+            if let Some((format, export)) = exported() {
+                a {
+                    class: "join-button",
+                    href: data_url(format.mime_type(), &export),
+                    download: "carbine-history.{format.extension()}",
+                    "Download {format.label()}"
+                }
+                textarea {
+                    class: "invoice-input export-preview",
+                    rows: 10,
+                    readonly: true,
+                    value: "{export}"
+                }
+            }
+        }
+    }
+}
+
+/// Builds the filter from the form. `until` is inclusive, so the export runs
+/// to the end of that day.
+fn build_filter(
+    from: &str,
+    until: &str,
+    excluded: &BTreeSet<FederationId>,
+) -> anyhow::Result<ExportFilter> {
+    let parse = |date: &str| (!date.trim().is_empty()).then(|| parse_date(date)).transpose();
+    let federations = (!excluded.is_empty()).then(|| {
+        FEDERATIONS()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.federation_id)
+            .filter(|id| !excluded.contains(id))
+            .collect()
+    });
+    Ok(ExportFilter {
+        from: parse(from)?,
+        until: parse(until)?.map(|until| until + Duration::from_secs(86400)),
+        federations,
+    })
+}
+
+/// A `data:` URL with `content`, so the export can be downloaded without
+/// writing a file ourselves.
+fn data_url(mime_type: &str, content: &str) -> String {
+    let mut url = format!("data:{mime_type};charset=utf-8,");
+    for byte in content.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    url
+}
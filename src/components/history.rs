@@ -0,0 +1,249 @@
+use std::collections::BTreeSet;
+
+use dioxus::prelude::*;
+use fedimint_client::OperationId;
+use fedimint_core::config::FederationId;
+
+use crate::{
+    db::OperationAnnotation,
+    federation_name,
+    multimint::HISTORY_LIMIT,
+    operations::{format_elapsed, OperationRecord},
+    units::format_amount,
+    Route, DISPLAY_UNIT, FEDERATION_STATUSES, PENDING_OPERATIONS, WALLET,
+};
+
+/// Recent operations of a federation, newest first.
+#[component]
+pub fn FederationHistory(id: FederationId) -> Element {
+    // Reloads once the federation becomes usable and whenever an operation
+    // starts or finishes
+    let operations = use_resource(use_reactive!(|id| async move {
+        let _ = FEDERATION_STATUSES.read().get(&id).cloned();
+        let _ = PENDING_OPERATIONS.read().len();
+        let mm = WALLET().multimint().await?;
+        mm.operations(&id, HISTORY_LIMIT).await
+    }));
+    let mut query = use_signal(|| String::new());
+    let mut tag_filter = use_signal(|| None::<String>);
+
+    let tags = use_memo(move || match &*operations.read() {
+        Some(Ok(operations)) => operations
+            .iter()
+            .flat_map(|record| record.annotation.tags.iter().cloned())
+            .collect::<BTreeSet<_>>(),
+        _ => BTreeSet::new(),
+    });
+    let shown = use_memo(move || match &*operations.read() {
+        Some(Ok(operations)) => operations
+            .iter()
+            .filter(|record| record.matches(&query()))
+            .filter(|record| tag_filter().is_none_or(|tag| record.annotation.tags.contains(&tag)))
+            .cloned()
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    });
+
+    rsx! {
+        div {
+            class: "screen",
+            Link { class: "back-link", to: Route::Federation { id }, "← {federation_name(&id)}" }
+            h2 { "History" }
+            match &*operations.read() {
+                Some(Ok(operations)) if operations.is_empty() => rsx! {
+                    p { class: "screen-hint", "No operations yet" }
+                },
+                Some(Ok(_)) => rsx! {
+                    input {
+                        class: "input-box",
+                        r#type: "search",
+                        placeholder: "Search labels, notes, tags and contacts",
+                        value: "{query}",
+                        oninput: move |evt| query.set(evt.value())
+                    }
+                    if !tags().is_empty() {
+                        div {
+                            class: "tag-filter",
+                            for tag in tags() {
+                                button {
+                                    key: "{tag}",
+                                    class: if tag_filter() == Some(tag.clone()) { "tag active" } else { "tag" },
+                                    onclick: {
+                                        let tag = tag.clone();
+                                        move |_| {
+                                            let selected = tag_filter() == Some(tag.clone());
+                                            tag_filter.set((!selected).then(|| tag.clone()));
+                                        }
+                                    },
+                                    "{tag}"
+                                }
+                            }
+                        }
+                    }
+                    div {
+                        class: "history-list",
+                        for record in shown() {
+                            HistoryItem { key: "{record.operation_id.fmt_full()}", record }
+                        }
+                    }
+                    if shown().is_empty() {
+                        p { class: "screen-hint", "No operations match" }
+                    }
+                },
+                Some(Err(e)) => rsx! {
+                    p { class: "balance-error", "{e.user_message(DISPLAY_UNIT())}" }
+                },
+                None => rsx! {
+                    div { class: "spinner" }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn HistoryItem(record: OperationRecord) -> Element {
+    rsx! {
+        Link {
+            class: "history-item",
+            to: Route::Operation { id: record.operation_id },
+            span { class: "pending-kind", "{record.kind.label()}" }
+            if !record.annotation.label.is_empty() {
+                span { class: "history-label", "{record.annotation.label}" }
+            }
+            for tag in record.annotation.tags.iter() {
+                span { class: "tag", "{tag}" }
+            }
+            if let Some(counterparty) = &record.counterparty {
+                span { class: "history-counterparty", "{counterparty}" }
+            }
+            if let Some(amount) = record.amount {
+                span { class: "history-amount", "{format_amount(amount, DISPLAY_UNIT())}" }
+            }
+            span { class: "pending-state", "{record.state()}" }
+            span { class: "pending-time", "{format_elapsed(record.started_at)}" }
+        }
+    }
+}
+
+/// Details of a single operation of any joined federation.
+#[component]
+pub fn Operation(id: OperationId) -> Element {
+    let record = use_resource(use_reactive!(|id| async move {
+        let _ = PENDING_OPERATIONS.read().len();
+        match WALLET().multimint().await {
+            Ok(mm) => mm.operation(id).await,
+            Err(_) => None,
+        }
+    }));
+
+    rsx! {
+        div {
+            class: "screen",
+            match record() {
+                Some(Some(record)) => rsx! {
+                    Link {
+                        class: "back-link",
+                        to: Route::FederationHistory { id: record.federation_id },
+                        "← {federation_name(&record.federation_id)}"
+                    }
+                    h2 { "{record.kind.label()}" }
+                    dl {
+                        class: "operation-details",
+                        if let Some(amount) = record.amount {
+                            dt { "Amount" }
+                            dd { "{format_amount(amount, DISPLAY_UNIT())}" }
+                        }
+                        if let Some(counterparty) = &record.counterparty {
+                            dt { "Contact" }
+                            dd { "{counterparty}" }
+                        }
+                        dt { "State" }
+                        dd { "{record.state()}" }
+                        dt { "Started" }
+                        dd { "{format_elapsed(record.started_at)}" }
+                        dt { "Operation ID" }
+                        dd { class: "operation-id", "{record.operation_id.fmt_full()}" }
+                    }
+                    AnnotationEditor {
+                        key: "{record.operation_id.fmt_full()}",
+                        operation_id: record.operation_id,
+                        annotation: record.annotation.clone()
+                    }
+                },
+                Some(None) => rsx! {
+                    p { class: "screen-hint", "This operation was not found in the recent history of your federations." }
+                },
+                None => rsx! {
+                    div { class: "spinner" }
+                },
+            }
+        }
+    }
+}
+
+/// Edits the label, note and tags of an operation.
+#[component]
+fn AnnotationEditor(operation_id: OperationId, annotation: OperationAnnotation) -> Element {
+    let mut label = use_signal(|| annotation.label.clone());
+    let mut note = use_signal(|| annotation.note.clone());
+    let mut tags = use_signal(|| annotation.tags.join(", "));
+    let mut saved = use_signal(|| false);
+
+    let on_save = move |_| {
+        let annotation = OperationAnnotation {
+            label: label().trim().to_string(),
+            note: note().trim().to_string(),
+            tags: OperationAnnotation::parse_tags(&tags()),
+        };
+        spawn(async move {
+            if let Ok(mm) = WALLET().multimint().await {
+                mm.annotate(operation_id, &annotation).await;
+                saved.set(true);
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            class: "annotation-editor",
+            h3 { "Notes" }
+            input {
+                class: "input-box",
+                r#type: "text",
+                placeholder: "Label",
+                value: "{label}",
+                oninput: move |evt| {
+                    label.set(evt.value());
+                    saved.set(false);
+                }
+            }
+            textarea {
+                class: "invoice-input",
+                rows: 3,
+                placeholder: "Note",
+                value: "{note}",
+                oninput: move |evt| {
+                    note.set(evt.value());
+                    saved.set(false);
+                }
+            }
+            input {
+                class: "input-box",
+                r#type: "text",
+                placeholder: "Tags, separated by commas",
+                value: "{tags}",
+                oninput: move |evt| {
+                    tags.set(evt.value());
+                    saved.set(false);
+                }
+            }
+            button {
+                class: "invoice-button",
+                onclick: on_save,
+                disabled: saved(),
+                if saved() { "Saved" } else { "Save" }
+            }
+        }
+    }
+}
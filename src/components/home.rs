@@ -0,0 +1,62 @@
+use dioxus::prelude::*;
+
+use crate::{Route, FEDERATIONS, SETTINGS};
+
+/// Start screen, shown until a federation is picked from the sidebar. Opens
+/// the default federation right away if one is set.
+#[component]
+pub fn Home() -> Element {
+    use_effect(move || {
+        let Some(id) = SETTINGS.read().default_federation else {
+            return;
+        };
+        let joined = FEDERATIONS()
+            .unwrap_or_default()
+            .iter()
+            .any(|item| item.federation_id == id);
+        if joined {
+            navigator().replace(Route::Federation { id });
+        }
+    });
+
+    rsx! {
+        div {
+            class: "screen",
+            match FEDERATIONS() {
+                None => rsx! {
+                    div { class: "spinner" }
+                },
+                Some(federations) if federations.is_empty() => rsx! {
+                    h2 { "Welcome to carbine" }
+                    p { "Join a federation to get started." }
+                    Link {
+                        class: "join-button",
+                        to: Route::Join { invite_code: String::new() },
+                        "Join Federation"
+                    }
+                    p {
+                        "No invite code? "
+                        Link { to: Route::Discover {}, "Discover federations" }
+                        " announced on Nostr."
+                    }
+                },
+                Some(_) => rsx! {
+                    p { class: "screen-hint", "Pick a federation from the sidebar, or paste a payment to pay it." }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+pub fn NotFound(segments: Vec<String>) -> Element {
+    let path = segments.join("/");
+    rsx! {
+        div {
+            class: "screen",
+            h2 { "Page not found" }
+            p { "Nothing lives at /{path}." }
+            Link { to: Route::Home {}, "Back to the start" }
+        }
+    }
+}
@@ -1,38 +1,52 @@
 use std::time::Duration;
 
-use dioxus::prelude::*;
+use dioxus::{logger::tracing::info, prelude::*};
 use fedimint_core::task::sleep;
 
-use crate::{load_multimint, FederationSelector};
+use crate::{FederationSelector, Route, DISPLAY_UNIT, WALLET};
 
+/// Join screen, prefilled with the invite code from the address if any.
 #[component]
-pub fn JoinFederationForm(on_join_success: EventHandler<FederationSelector>) -> Element {
-    let mut input_value = use_signal(|| String::new());
+pub fn Join(invite_code: String) -> Element {
+    rsx! {
+        JoinFederationForm {
+            key: "{invite_code}",
+            initial_invite_code: invite_code.clone(),
+            on_join_success: move |selector: FederationSelector| {
+                navigator().replace(Route::Federation { id: selector.federation_id });
+            }
+        }
+    }
+}
+
+#[component]
+pub fn JoinFederationForm(
+    initial_invite_code: String,
+    on_join_success: EventHandler<FederationSelector>,
+) -> Element {
+    let mut input_value = use_signal(|| initial_invite_code);
     let error_message = use_signal(|| None::<String>); // Add signal for errors
 
     let on_join = {
         to_owned![input_value, error_message, on_join_success];
         move || {
             spawn(async move {
-                let multimint = load_multimint().await;
-                let mut mm = multimint.write().await;
-                if let Some(mm) = mm.as_mut() {
-                    match mm.join_federation(input_value()).await {
-                        Ok(selector) => {
-                            input_value.set(String::new());
-                            error_message.set(None); // clear errors
-                            on_join_success.call(selector);
-                        }
-                        Err(_) => {
-                            error_message.set(Some(format!("Could not join federation")));
-                            spawn({
-                                to_owned![error_message];
-                                async move {
-                                    sleep(Duration::from_secs(4)).await;
-                                    error_message.set(None);
-                                }
-                            });
-                        }
+                match WALLET().join_federation(input_value()).await {
+                    Ok(selector) => {
+                        input_value.set(String::new());
+                        error_message.set(None); // clear errors
+                        on_join_success.call(selector);
+                    }
+                    Err(e) => {
+                        info!("Join returning error: {e}");
+                        error_message.set(Some(e.user_message(DISPLAY_UNIT())));
+                        spawn({
+                            to_owned![error_message];
+                            async move {
+                                sleep(Duration::from_secs(4)).await;
+                                error_message.set(None);
+                            }
+                        });
                     }
                 }
             });
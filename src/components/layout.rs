@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use dioxus::{logger::tracing::warn, prelude::*};
+use fedimint_core::{task::sleep, time::now};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    components::{paste::PasteBox, pin::PinDialog},
+    federation_name, lnurl,
+    notifications::{platform_notifier, Notification},
+    parser::PaymentString,
+    service::WalletEvent,
+    DashboardAction, PinPrompt, Route, FEDERATIONS, FEDERATION_STATUSES, FIAT_CURRENCY, FIAT_RATE,
+    DISPLAY_UNIT, NOTICE_DURATION, PENDING_OPERATIONS, PIN_PROMPT, PRICE_REFRESH_INTERVAL,
+    PRICE_SOURCE, SETTINGS, SETTINGS_ERROR, WALLET,
+};
+
+/// How often the idle time is checked against the auto-lock timeout.
+const LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Sidebar, paste box and notices around the current screen. Also keeps the
+/// global wallet state in sync while the app is open.
+#[component]
+pub fn Shell() -> Element {
+    let route = use_route::<Route>();
+    let mut dashboard_action = use_context_provider(|| Signal::new(None::<DashboardAction>));
+    let mut paste_error = use_signal(|| None::<String>);
+    let mut notice = use_signal(|| None::<String>);
+    let mut last_activity = use_signal(now);
+
+    let load_items = move || {
+        spawn(async move {
+            if let Ok(mm) = WALLET().multimint().await {
+                *FEDERATIONS.write() = Some(mm.federations().await);
+            }
+        });
+    };
+
+    use_future(move || async move {
+        if let Ok(mm) = WALLET().multimint().await {
+            match mm.settings().await {
+                Ok(settings) => *SETTINGS.write() = settings,
+                Err(e) => {
+                    warn!("Could not read settings: {e}");
+                    *SETTINGS_ERROR.write() = Some(e.user_message(DISPLAY_UNIT()));
+                }
+            }
+        }
+        // A wallet that locks when idle also starts locked
+        let settings = SETTINGS.read();
+        if settings.pin.is_some() && settings.auto_lock_minutes.is_some() {
+            *PIN_PROMPT.write() = Some(PinPrompt::Unlock);
+        }
+    });
+
+    // Lock the wallet once it was idle for longer than the user allows
+    use_future(move || async move {
+        loop {
+            sleep(LOCK_CHECK_INTERVAL).await;
+            let settings = SETTINGS();
+            let (Some(_), Some(minutes)) = (settings.pin, settings.auto_lock_minutes) else {
+                continue;
+            };
+            let idle = now().duration_since(last_activity()).unwrap_or_default();
+            if idle >= Duration::from_secs(u64::from(minutes) * 60) {
+                *PIN_PROMPT.write() = Some(PinPrompt::Unlock);
+            }
+        }
+    });
+
+    // Refresh the sidebar whenever the status of a federation changes
+    use_future(move || async move {
+        let mut statuses = WALLET().statuses();
+        loop {
+            *FEDERATION_STATUSES.write() = statuses.borrow_and_update().clone();
+            load_items();
+            if statuses.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    use_future(move || async move {
+        let mut pending = WALLET().pending();
+        loop {
+            *PENDING_OPERATIONS.write() = pending.borrow_and_update().clone();
+            if pending.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Tell the user how operations ended, even if their screen was closed
+    use_future(move || async move {
+        let notifier = platform_notifier();
+        let mut events = WALLET().events();
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let WalletEvent::OperationFinished { operation, outcome } = &event;
+            let federation_name = federation_name(&operation.federation_id);
+
+            let notification = Notification::for_event(&event, &federation_name)
+                .filter(|_| SETTINGS.read().notifications.allows(operation.kind));
+            if let Some(notification) = notification {
+                if let Err(e) = notifier.notify(&notification) {
+                    warn!("Could not show notification: {e}");
+                }
+            }
+            notice.set(Some(format!(
+                "{}: {}",
+                federation_name,
+                outcome.describe(operation.kind)
+            )));
+            spawn(async move {
+                sleep(NOTICE_DURATION).await;
+                notice.set(None);
+            });
+        }
+    });
+
+    // Keep the fiat rate fresh while a currency is selected
+    use_resource(move || async move {
+        let source = PRICE_SOURCE();
+        let Some(currency) = FIAT_CURRENCY() else {
+            *FIAT_RATE.write() = None;
+            return;
+        };
+        loop {
+            let rate = match WALLET().multimint().await {
+                Ok(mm) => mm.fiat_rate(&source, &currency).await,
+                Err(_) => None,
+            };
+            *FIAT_RATE.write() = rate;
+            sleep(PRICE_REFRESH_INTERVAL).await;
+        }
+    });
+
+    let on_paste = move |parsed: PaymentString| {
+        // Payments go to the federation on screen, the default one or the first
+        let current = match &route {
+            Route::Federation { id } | Route::FederationHistory { id } => Some(*id),
+            _ => None,
+        };
+        let route = route.clone();
+        spawn(async move {
+            paste_error.set(None);
+            let mut target = current
+                .or(SETTINGS.read().default_federation)
+                .or_else(|| {
+                    FEDERATIONS()
+                        .unwrap_or_default()
+                        .first()
+                        .map(|item| item.federation_id)
+                });
+            let action = match parsed {
+                PaymentString::InviteCode(invite_code) => {
+                    navigator().push(Route::Join {
+                        invite_code: invite_code.to_string(),
+                    });
+                    return;
+                }
+                PaymentString::Ecash(notes) => {
+                    // Ecash can only be redeemed in the federation that issued it
+                    let issuer = WALLET()
+                        .multimint()
+                        .await
+                        .ok()
+                        .and_then(|mm| mm.federation_for_notes(&notes));
+                    match issuer {
+                        Some(id) => target = Some(id),
+                        None => {
+                            paste_error.set(Some(
+                                "This ecash was issued by a federation you have not joined"
+                                    .to_string(),
+                            ));
+                            return;
+                        }
+                    }
+                    DashboardAction::Redeem(notes.to_string())
+                }
+                PaymentString::Bolt11(invoice) => DashboardAction::Send(invoice.to_string()),
+                PaymentString::Bip21 {
+                    lightning: Some(invoice),
+                    ..
+                } => DashboardAction::Send(invoice.to_string()),
+                PaymentString::Bip21 {
+                    address,
+                    amount,
+                    lightning: None,
+                } => DashboardAction::Withdraw {
+                    address: address.assume_checked_ref().to_string(),
+                    amount_sats: amount.map(|amount| amount.to_sat()),
+                },
+                PaymentString::OnchainAddress(address) => DashboardAction::Withdraw {
+                    address: address.assume_checked_ref().to_string(),
+                    amount_sats: None,
+                },
+                PaymentString::LightningAddress(address) => DashboardAction::Send(address),
+                PaymentString::Lnurl(lnurl) => match lnurl::fetch_tag(&lnurl).await {
+                    Ok(tag) if tag == "withdrawRequest" => DashboardAction::Receive(lnurl),
+                    Ok(_) => DashboardAction::Send(lnurl),
+                    Err(e) => {
+                        paste_error.set(Some(format!("Could not resolve LNURL: {e}")));
+                        return;
+                    }
+                },
+            };
+
+            let Some(id) = target else {
+                paste_error.set(Some("Join a federation first".to_string()));
+                return;
+            };
+            dashboard_action.set(Some(action));
+            let destination = Route::Federation { id };
+            if route != destination {
+                navigator().push(destination);
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            class: "container",
+            onclick: move |_| last_activity.set(now()),
+            onkeydown: move |_| last_activity.set(now()),
+            // Sidebar
+            div {
+                class: "sidebar",
+                h2 { class: "sidebar-title", "Federations" }
+                ul {
+                    class: "sidebar-list",
+                    for item in FEDERATIONS().unwrap_or_default() {
+                        li {
+                            key: "{item.federation_id}",
+                            Link {
+                                class: "sidebar-item",
+                                active_class: "active",
+                                to: Route::Federation { id: item.federation_id },
+                                if let Some(url) = &item.meta.icon_url {
+                                    img { class: "federation-icon small", src: "{url}", alt: "" }
+                                }
+                                span { "{item}" }
+                                if let Some(warning) = item.meta.shutdown_warning() {
+                                    span { class: "status-badge status-failed", title: "{warning}", "Closing" }
+                                }
+                                if let Some(status) = FEDERATION_STATUSES().get(&item.federation_id) {
+                                    span {
+                                        class: "status-badge {status.css_class()}",
+                                        title: "{status.label()}",
+                                        "{status.label()}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div {
+                    class: "sidebar-footer",
+                    Link {
+                        class: "sidebar-link",
+                        active_class: "active",
+                        to: Route::Contacts {},
+                        "Contacts"
+                    }
+                    Link {
+                        class: "sidebar-link",
+                        active_class: "active",
+                        to: Route::Discover {},
+                        "Discover"
+                    }
+                    Link {
+                        class: "sidebar-link",
+                        active_class: "active",
+                        to: Route::Export {},
+                        "Export"
+                    }
+                    Link {
+                        class: "sidebar-link",
+                        active_class: "active",
+                        to: Route::Settings {},
+                        "Settings"
+                    }
+                }
+            }
+
+            // Main content
+            div {
+                class: "main",
+
+                PasteBox { on_parsed: on_paste }
+                if let Some(msg) = paste_error() {
+                    div {
+                        class: "toast-error",
+                        "{msg}"
+                    }
+                }
+                if let Some(msg) = notice() {
+                    div {
+                        class: "toast-info",
+                        "{msg}"
+                    }
+                }
+
+                Link {
+                    class: "add-button",
+                    to: Route::Join { invite_code: String::new() },
+                    "+"
+                }
+
+                Outlet::<Route> {}
+            }
+
+            if let Some(prompt) = PIN_PROMPT() {
+                PinDialog { key: "{prompt:?}", prompt }
+            }
+        }
+    }
+}
@@ -1,4 +1,19 @@
+pub mod contacts;
 pub mod dashboard;
+pub mod deposit;
+pub mod discover;
+pub mod export;
+pub mod history;
+pub mod home;
 pub mod join;
+pub mod layout;
+pub mod paste;
+pub mod pending;
+pub mod pin;
+pub mod qr;
 pub mod receive;
+pub mod redeem;
 pub mod send;
+pub mod settings;
+pub mod spend;
+pub mod withdraw;
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use dioxus::{
+    html::{FileEngine, HasFileData},
+    prelude::*,
+};
+
+use crate::{
+    parser::{parse_payment_string, PaymentString},
+    scan,
+};
+
+#[component]
+pub fn PasteBox(on_parsed: EventHandler<PaymentString>) -> Element {
+    let mut input_value = use_signal(|| String::new());
+    let mut error_message = use_signal(|| None::<String>);
+    let detected = use_memo(move || {
+        parse_payment_string(&input_value())
+            .ok()
+            .map(|parsed| parsed.kind())
+    });
+
+    let mut submit = move |input: String| match parse_payment_string(&input) {
+        Ok(parsed) => {
+            input_value.set(String::new());
+            error_message.set(None);
+            on_parsed.call(parsed);
+        }
+        Err(e) => {
+            error_message.set(Some(e.to_string()));
+        }
+    };
+
+    // Feeds the first decodable QR code into the same flow as pasted text
+    let mut submit_scanned = move |scanned: anyhow::Result<Vec<String>>| match scanned {
+        Ok(contents) => match contents.into_iter().next() {
+            Some(content) => submit(content),
+            None => error_message.set(Some("No QR code found in image".to_string())),
+        },
+        Err(e) => error_message.set(Some(e.to_string())),
+    };
+
+    let on_files = move |files: Option<Arc<dyn FileEngine>>| async move {
+        let Some(files) = files else {
+            return;
+        };
+        for name in files.files() {
+            if let Some(bytes) = files.read_file(&name).await {
+                submit_scanned(scan::decode_qr_image(&bytes));
+                return;
+            }
+        }
+    };
+
+    rsx! {
+        div {
+            class: "paste-box",
+            ondragover: move |evt| evt.prevent_default(),
+            ondrop: move |evt| {
+                evt.prevent_default();
+                on_files(evt.files())
+            },
+            div {
+                class: "paste-row",
+                input {
+                    class: "paste-input",
+                    r#type: "text",
+                    placeholder: "Paste or drop an invoice, address, invite code, ecash, LNURL or QR image...",
+                    value: "{input_value}",
+                    oninput: move |evt| {
+                        input_value.set(evt.value().clone());
+                        error_message.set(None);
+                    }
+                }
+                button {
+                    class: "paste-button",
+                    onclick: move |_| submit(input_value()),
+                    "Go"
+                }
+            }
+            div {
+                class: "paste-row paste-image-row",
+                label {
+                    class: "paste-image-button",
+                    "Open QR Image"
+                    input {
+                        class: "paste-file-input",
+                        r#type: "file",
+                        accept: ".png,.jpg,.jpeg",
+                        onchange: move |evt| on_files(evt.files())
+                    }
+                }
+                if cfg!(feature = "desktop") {
+                    button {
+                        class: "paste-image-button",
+                        onclick: move |_| submit_scanned(scan_clipboard()),
+                        "Paste QR Image"
+                    }
+                }
+            }
+
+            if let Some(msg) = error_message() {
+                div {
+                    class: "paste-error",
+                    "{msg}"
+                }
+            } else if let Some(kind) = detected() {
+                div {
+                    class: "paste-detected",
+                    "{kind} detected"
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "desktop")]
+fn scan_clipboard() -> anyhow::Result<Vec<String>> {
+    scan::decode_qr_clipboard()
+}
+
+#[cfg(not(feature = "desktop"))]
+fn scan_clipboard() -> anyhow::Result<Vec<String>> {
+    anyhow::bail!("Pasting images is only supported on desktop")
+}
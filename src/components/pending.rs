@@ -0,0 +1,38 @@
+use dioxus::prelude::*;
+use fedimint_core::config::FederationId;
+
+use crate::{operations::format_elapsed, PENDING_OPERATIONS};
+
+/// Unfinished operations of a federation, including ones started before the
+/// app was last closed.
+#[component]
+pub fn PendingOperations(federation_id: FederationId) -> Element {
+    let operations = use_memo(move || {
+        let mut operations = PENDING_OPERATIONS()
+            .into_iter()
+            .filter(|operation| operation.federation_id == federation_id)
+            .collect::<Vec<_>>();
+        operations.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        operations
+    });
+
+    if operations().is_empty() {
+        return rsx! {};
+    }
+
+    rsx! {
+        div {
+            class: "pending-list",
+            h3 { "Pending" }
+            for operation in operations() {
+                div {
+                    key: "{operation.operation_id.fmt_full()}",
+                    class: "pending-item",
+                    span { class: "pending-kind", "{operation.kind.label()}" }
+                    span { class: "pending-state", "{operation.kind.waiting_for()}" }
+                    span { class: "pending-time", "{format_elapsed(operation.started_at)}" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use dioxus::prelude::*;
+
+use crate::{
+    error::CarbineError, limits::PinPurpose, PinPrompt, DISPLAY_UNIT, PIN_PROMPT, SETTINGS, WALLET,
+};
+
+/// Asks for the PIN if `error` is a payment that needs confirming, so the user
+/// can retry it once the PIN was entered.
+pub(crate) fn ask_pin_if_required(error: &CarbineError) {
+    if matches!(error, CarbineError::ConfirmationRequired(_)) && PIN_PROMPT().is_none() {
+        *PIN_PROMPT.write() = Some(PinPrompt::Confirm);
+    }
+}
+
+/// Checks `pin` for `purpose`. On success the settings are reloaded, as an
+/// outdated PIN hash is replaced while checking it.
+pub(crate) async fn authenticate(pin: String, purpose: PinPurpose) -> Result<(), CarbineError> {
+    let wallet = WALLET();
+    wallet.authenticate(pin, purpose).await?;
+    *SETTINGS.write() = wallet.multimint().await?.settings().await?;
+    Ok(())
+}
+
+/// Asks for the PIN over the current screen. A locked wallet can not be used
+/// until it is entered, a confirmation can be cancelled.
+#[component]
+pub fn PinDialog(prompt: PinPrompt) -> Element {
+    let mut pin = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut checking = use_signal(|| false);
+
+    let mut on_submit = move || {
+        checking.set(true);
+        spawn(async move {
+            let purpose = match prompt {
+                PinPrompt::Unlock => PinPurpose::Unlock,
+                PinPrompt::Confirm => PinPurpose::ConfirmPayment,
+            };
+            match authenticate(pin(), purpose).await {
+                Ok(()) => *PIN_PROMPT.write() = None,
+                Err(e) => {
+                    pin.set(String::new());
+                    error.set(Some(e.user_message(DISPLAY_UNIT())));
+                }
+            }
+            checking.set(false);
+        });
+    };
+
+    rsx! {
+        div {
+            class: if prompt == PinPrompt::Unlock { "modal-overlay lock-overlay" } else { "modal-overlay" },
+            div {
+                class: "modal-content",
+                if prompt == PinPrompt::Unlock {
+                    h3 { "Wallet locked" }
+                    p { class: "screen-hint", "Enter your PIN to unlock carbine." }
+                } else {
+                    h3 { "Confirm payment" }
+                    p { class: "screen-hint", "Enter your PIN, then send the payment again." }
+                }
+                input {
+                    class: "input-box",
+                    r#type: "password",
+                    inputmode: "numeric",
+                    autofocus: true,
+                    placeholder: "PIN",
+                    value: "{pin}",
+                    oninput: move |evt| pin.set(evt.value()),
+                    onkeydown: move |evt| {
+                        if evt.key() == Key::Enter {
+                            on_submit();
+                        }
+                    }
+                }
+                div {
+                    class: "button-row",
+                    button {
+                        class: "invoice-button",
+                        disabled: checking() || pin().is_empty(),
+                        onclick: move |_| on_submit(),
+                        if prompt == PinPrompt::Unlock { "Unlock" } else { "Confirm" }
+                    }
+                    if prompt == PinPrompt::Confirm {
+                        button {
+                            class: "secondary-button",
+                            onclick: move |_| *PIN_PROMPT.write() = None,
+                            "Cancel"
+                        }
+                    }
+                }
+                if let Some(msg) = error() {
+                    div { class: "toast-error", "{msg}" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use fedimint_core::task::sleep;
+use qrcode::{render::svg, EcLevel, QrCode};
+
+/// How long the copy button says "Copied" before it can be used again.
+const COPIED_DURATION: Duration = Duration::from_secs(2);
+
+/// Renders `value` as a QR code with a copy-to-clipboard button next to it.
+#[component]
+pub fn QrDisplay(value: String) -> Element {
+    let svg = use_memo({
+        to_owned![value];
+        move || qr_svg(&value)
+    });
+    let mut copied = use_signal(|| false);
+
+    let on_copy = {
+        to_owned![value];
+        move |_| {
+            copy_to_clipboard(&value);
+            copied.set(true);
+            spawn(async move {
+                sleep(COPIED_DURATION).await;
+                copied.set(false);
+            });
+        }
+    };
+
+    rsx! {
+        div {
+            class: "qr-container",
+            match svg() {
+                Some(svg) => rsx! {
+                    div { class: "qr-code", dangerous_inner_html: "{svg}" }
+                },
+                None => rsx! {
+                    p { class: "qr-error", "Too long to display as a QR code" }
+                }
+            }
+            div {
+                class: "qr-value-row",
+                div { class: "invoice-output qr-value", "{value}" }
+                button {
+                    class: "copy-button",
+                    onclick: on_copy,
+                    if copied() { "Copied" } else { "Copy" }
+                }
+            }
+        }
+    }
+}
+
+fn qr_svg(value: &str) -> Option<String> {
+    let code = QrCode::with_error_correction_level(qr_payload(value), EcLevel::L).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(240, 240)
+            .quiet_zone(true)
+            .build(),
+    )
+}
+
+/// Bech32 strings (invoices, segwit addresses, invite codes) are case
+/// insensitive, and their uppercase form fits the denser alphanumeric QR
+/// encoding mode. Anything else is encoded as is.
+fn qr_payload(value: &str) -> String {
+    if bech32::decode(value).is_ok() {
+        value.to_ascii_uppercase()
+    } else {
+        value.to_owned()
+    }
+}
+
+pub(crate) fn copy_to_clipboard(value: &str) {
+    let value = serde_json::to_string(value).expect("Strings serialize to JSON");
+    document::eval(&format!("navigator.clipboard.writeText({value});"));
+}
@@ -1,46 +1,178 @@
 use dioxus::{logger::tracing::info, prelude::*};
-use fedimint_core::Amount;
+use fedimint_core::bitcoin::hashes::{sha256, Hash};
 use fedimint_lnv2_client::FinalReceiveOperationState;
+use fedimint_lnv2_common::Bolt11InvoiceDescription;
 
-use crate::{load_multimint, FederationSelector};
+use crate::{
+    components::qr::QrDisplay,
+    db::OperationAnnotation,
+    lnurl::{self, WithdrawRequest},
+    multimint::DEFAULT_EXPIRY_TIME_SECS,
+    units::{format_amount, format_number, parse_amount},
+    FederationSelector, DISPLAY_UNIT, FIAT_RATE, WALLET,
+};
+
+const EXPIRY_OPTIONS: [(&str, u32); 4] = [
+    ("10 minutes", 600),
+    ("1 hour", 3600),
+    ("1 day", DEFAULT_EXPIRY_TIME_SECS),
+    ("1 week", 604800),
+];
 
 #[component]
-pub fn Receive(federation_info: FederationSelector) -> Element {
-    let mut amount_msats = use_signal(|| "".to_string());
+pub fn Receive(
+    federation_info: FederationSelector,
+    #[props(default)] initial_lnurlw: String,
+) -> Element {
+    let mut amount_input = use_signal(|| "".to_string());
     let mut invoice = use_signal(|| None::<String>);
+    let mut qr_invoice = use_signal(|| None::<String>);
+    let mut lnurlw_code = use_signal(|| initial_lnurlw);
+    let mut withdraw_request = use_signal(|| None::<WithdrawRequest>);
+    let mut fiat_entry = use_signal(|| false);
+    let mut memo = use_signal(|| "".to_string());
+    let mut label = use_signal(|| "".to_string());
+    let mut use_description_hash = use_signal(|| false);
+    let mut expiry_secs = use_signal(|| DEFAULT_EXPIRY_TIME_SECS);
+
+    // Amount as entered, either in the display unit or in fiat at the quoted rate
+    let entered_amount = use_memo(move || match (fiat_entry(), FIAT_RATE()) {
+        (true, Some(rate)) => rate.parse(&amount_input()).map_err(|e| e.to_string()),
+        _ => parse_amount(&amount_input(), DISPLAY_UNIT()).map_err(|e| e.to_string()),
+    });
+
+    let load_withdraw_request = move |_| {
+        spawn({
+            async move {
+                match lnurl::resolve_withdraw(&lnurlw_code()).await {
+                    Ok(request) => {
+                        fiat_entry.set(false);
+                        amount_input.set(format_number(
+                            request.max_withdrawable(),
+                            DISPLAY_UNIT(),
+                        ));
+                        withdraw_request.set(Some(request));
+                        invoice.set(None);
+                    }
+                    Err(e) => {
+                        info!("Could not resolve LNURL-withdraw: {e}");
+                        withdraw_request.set(None);
+                        invoice.set(Some(format!("Could not resolve LNURL: {e}")));
+                    }
+                }
+            }
+        });
+    };
 
     let generate_invoice = move |_| {
         spawn({
             async move {
-                let amount_msats = amount_msats().trim().parse::<u64>();
-                match amount_msats {
-                    Ok(msats) if msats > 0 => {
-                        let amount = Amount::from_msats(msats);
-                        let multimint = load_multimint().await;
-                        let mm = multimint.read().await;
-                        if let Some(mm) = mm.as_ref() {
-                            match mm.receive(&federation_info.federation_id, amount).await {
-                                Ok((generated_invoice, operation_id)) => {
-                                    invoice.set(Some(generated_invoice));
-
-                                    match mm
-                                        .await_receive(&federation_info.federation_id, operation_id)
-                                        .await
-                                    {
-                                        Ok(FinalReceiveOperationState::Claimed) => {
-                                            invoice.set(Some(format!("Received payment!")));
+                let unit = DISPLAY_UNIT();
+                match entered_amount() {
+                    Ok(amount) => {
+                        let withdraw_request = withdraw_request();
+                        if let Some(request) = &withdraw_request {
+                            if amount < request.min_withdrawable()
+                                || amount > request.max_withdrawable()
+                            {
+                                invoice.set(Some(format!(
+                                    "Amount must be between {} and {}",
+                                    format_amount(request.min_withdrawable(), unit),
+                                    format_amount(request.max_withdrawable(), unit)
+                                )));
+                                return;
+                            }
+                        }
+
+                        // LNURL-withdraw services expect their default description
+                        let description = match &withdraw_request {
+                            Some(request) => {
+                                Bolt11InvoiceDescription::Direct(request.default_description.clone())
+                            }
+                            None if use_description_hash() => Bolt11InvoiceDescription::Hash(
+                                sha256::Hash::hash(memo().as_bytes()),
+                            ),
+                            None => Bolt11InvoiceDescription::Direct(memo().trim().to_string()),
+                        };
+
+                        match WALLET().multimint().await {
+                            Ok(mm) => {
+                                match mm
+                                    .receive(
+                                        &federation_info.federation_id,
+                                        amount,
+                                        description,
+                                        expiry_secs(),
+                                    )
+                                    .await
+                                {
+                                    Ok((generated_invoice, operation_id)) => {
+                                        WALLET()
+                                            .track_pending(federation_info.federation_id)
+                                            .await;
+                                        if !label().trim().is_empty() {
+                                            let annotation =
+                                                OperationAnnotation::with_label(&label());
+                                            mm.annotate(operation_id, &annotation).await;
+                                        }
+                                        if let Some(request) = &withdraw_request {
+                                            if let Err(e) =
+                                                lnurl::submit_withdraw(request, &generated_invoice)
+                                                    .await
+                                            {
+                                                info!("LNURL-withdraw was rejected: {e}");
+                                                invoice.set(Some(format!(
+                                                    "Withdraw was rejected by the service: {e}"
+                                                )));
+                                                return;
+                                            }
+                                            invoice.set(Some(String::from(
+                                                "Waiting for the service to pay...",
+                                            )));
+                                        } else {
+                                            invoice.set(None);
+                                            qr_invoice.set(Some(generated_invoice));
+                                        }
+
+                                        match mm
+                                            .await_receive(
+                                                &federation_info.federation_id,
+                                                operation_id,
+                                            )
+                                            .await
+                                        {
+                                            Ok(FinalReceiveOperationState::Claimed) => {
+                                                qr_invoice.set(None);
+                                                invoice.set(Some("Received payment!".to_string()));
+                                            }
+                                            Ok(FinalReceiveOperationState::Expired) => {
+                                                qr_invoice.set(None);
+                                                invoice.set(Some(String::from(
+                                                    "Invoice expired before it was paid",
+                                                )));
+                                            }
+                                            Ok(_) => {
+                                                qr_invoice.set(None);
+                                                invoice.set(Some(String::from(
+                                                    "Payment was received but could not be claimed",
+                                                )));
+                                            }
+                                            Err(e) => {
+                                                invoice.set(Some(e.user_message(DISPLAY_UNIT())));
+                                            }
                                         }
-                                        _ => {}
                                     }
-                                }
-                                Err(e) => {
-                                    info!("Receive returning error: {e}");
+                                    Err(e) => {
+                                        info!("Receive returning error: {e}");
+                                        invoice.set(Some(e.user_message(DISPLAY_UNIT())));
+                                    }
                                 }
                             }
+                            Err(e) => invoice.set(Some(e.user_message(DISPLAY_UNIT()))),
                         }
                     }
-                    _ => {
-                        invoice.set(Some("Invalid amount".to_string()));
+                    Err(e) => {
+                        invoice.set(Some(e));
                     }
                 }
             }
@@ -55,18 +187,127 @@ pub fn Receive(federation_info: FederationSelector) -> Element {
                 "Create Lightning Invoice"
             }
 
+            div {
+                class: "lnurl-row",
+                input {
+                    class: "invoice-input",
+                    r#type: "text",
+                    placeholder: "LNURL-withdraw code (optional)",
+                    value: "{lnurlw_code}",
+                    oninput: move |e| {
+                        lnurlw_code.set(e.value().clone());
+                        withdraw_request.set(None);
+                    }
+                }
+                button {
+                    class: "lnurl-load-button",
+                    onclick: load_withdraw_request,
+                    "Load"
+                }
+            }
+
+            if let Some(request) = withdraw_request() {
+                div {
+                    class: "lnurl-details",
+                    if !request.default_description.is_empty() {
+                        p { class: "lnurl-description", "{request.default_description}" }
+                    }
+                    p {
+                        class: "lnurl-range",
+                        "Withdraw between {format_amount(request.min_withdrawable(), DISPLAY_UNIT())} and {format_amount(request.max_withdrawable(), DISPLAY_UNIT())}"
+                    }
+                }
+            }
+
             input {
                 class: "invoice-input",
-                r#type: "number",
-                placeholder: "Amount in msats",
-                value: "{amount_msats}",
-                oninput: move |e| amount_msats.set(e.value().clone())
+                r#type: "text",
+                inputmode: "decimal",
+                placeholder: match (fiat_entry(), FIAT_RATE()) {
+                    (true, Some(rate)) => format!("Amount in {}", rate.currency),
+                    _ => DISPLAY_UNIT().placeholder(),
+                },
+                value: "{amount_input}",
+                oninput: move |e| amount_input.set(e.value().clone())
+             }
+
+             if let Some(rate) = FIAT_RATE() {
+                div {
+                    class: "fiat-entry-row",
+                    label {
+                        input {
+                            r#type: "checkbox",
+                            checked: fiat_entry(),
+                            disabled: withdraw_request().is_some(),
+                            onchange: move |e| fiat_entry.set(e.checked())
+                        }
+                        " Enter amount in {rate.currency}"
+                    }
+                    if let Ok(amount) = entered_amount() {
+                        span {
+                            class: "fiat-text",
+                            if fiat_entry() {
+                                "≈ {format_amount(amount, DISPLAY_UNIT())}"
+                            } else {
+                                "≈ {rate.format(amount)}"
+                            }
+                        }
+                    }
+                }
+             }
+
+             if withdraw_request().is_none() {
+                input {
+                    class: "invoice-input",
+                    r#type: "text",
+                    placeholder: if use_description_hash() { "Text to hash into the invoice" } else { "Memo (optional)" },
+                    value: "{memo}",
+                    oninput: move |e| memo.set(e.value().clone())
+                }
+                div {
+                    class: "fiat-entry-row",
+                    label {
+                        input {
+                            r#type: "checkbox",
+                            checked: use_description_hash(),
+                            onchange: move |e| use_description_hash.set(e.checked())
+                        }
+                        " Use description hash"
+                    }
+                    label {
+                        "Expires in "
+                        select {
+                            class: "expiry-select",
+                            value: "{expiry_secs}",
+                            onchange: move |e| {
+                                if let Ok(secs) = e.value().parse::<u32>() {
+                                    expiry_secs.set(secs);
+                                }
+                            },
+                            for (label, secs) in EXPIRY_OPTIONS {
+                                option { value: "{secs}", "{label}" }
+                            }
+                        }
+                    }
+                }
+             }
+
+             input {
+                class: "invoice-input",
+                r#type: "text",
+                placeholder: "Label (only visible to you)",
+                value: "{label}",
+                oninput: move |e| label.set(e.value().clone())
              }
 
              button {
                 class: "invoice-button",
                 onclick: generate_invoice,
-                "Generate Invoice"
+                if withdraw_request().is_some() { "Withdraw" } else { "Generate Invoice" }
+             }
+
+             if let Some(qr_invoice) = qr_invoice() {
+                QrDisplay { value: qr_invoice }
              }
 
              if let Some(invoice) = invoice() {
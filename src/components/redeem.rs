@@ -0,0 +1,109 @@
+use std::str::FromStr;
+
+use dioxus::{logger::tracing::info, prelude::*};
+use fedimint_mint_client::OOBNotes;
+
+use crate::{units::format_amount, FederationSelector, DISPLAY_UNIT, FIAT_RATE, WALLET};
+
+#[component]
+pub fn Redeem(
+    federation_info: FederationSelector,
+    #[props(default)] initial_notes: String,
+) -> Element {
+    let mut notes = use_signal(|| initial_notes);
+    let redeeming = use_signal(|| false);
+    let result = use_signal(|| None::<String>);
+
+    let parsed_notes = use_memo(move || OOBNotes::from_str(notes().trim()).ok());
+
+    let on_redeem = {
+        to_owned![redeeming, result];
+
+        move |_| {
+            let Some(oob_notes) = parsed_notes() else {
+                result.set(Some("Invalid ecash".to_string()));
+                return;
+            };
+
+            redeeming.set(true);
+            result.set(None);
+
+            spawn({
+                to_owned![redeeming, result];
+                async move {
+                    match WALLET().multimint().await {
+                        Ok(mm) => {
+                            match mm
+                                .redeem_ecash(&federation_info.federation_id, oob_notes)
+                                .await
+                            {
+                                Ok(operation_id) => {
+                                    WALLET().track_pending(federation_info.federation_id).await;
+                                    result.set(Some("Redeeming ecash...".to_string()));
+
+                                    match mm
+                                        .await_redeem(&federation_info.federation_id, operation_id)
+                                        .await
+                                    {
+                                        Ok(()) => {
+                                            result.set(Some(String::from(
+                                                "Ecash redeemed successfully",
+                                            )));
+                                        }
+                                        Err(e) => {
+                                            result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    info!("Redeem returning error: {e}");
+                                    result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                }
+                            }
+                        }
+                        Err(e) => result.set(Some(e.user_message(DISPLAY_UNIT()))),
+                    }
+                    redeeming.set(false);
+                }
+            });
+        }
+    };
+
+    rsx! {
+        div {
+            class: "invoice-container",
+            h2 { class: "invoice-title", "Redeem Ecash" }
+            textarea {
+                class: "invoice-input",
+                rows: 4,
+                value: "{notes}",
+                oninput: move |e| notes.set(e.value().clone()),
+                placeholder: "Paste ecash notes..."
+            }
+
+            if let Some(oob_notes) = parsed_notes() {
+                p {
+                    class: "lnurl-range",
+                    "Contains {format_amount(oob_notes.total_amount(), DISPLAY_UNIT())}"
+                    if let Some(rate) = FIAT_RATE() {
+                        " (≈ {rate.format(oob_notes.total_amount())})"
+                    }
+                }
+            }
+
+            button {
+                class: "invoice-button",
+                onclick: on_redeem,
+                disabled: "{redeeming()}",
+                "Redeem"
+            }
+
+            if let Some(res) = result() {
+                div {
+                    class: "invoice-output",
+                    "{res}"
+                }
+            }
+        }
+    }
+}
@@ -1,58 +1,157 @@
+use std::str::FromStr;
+
 use dioxus::{logger::tracing::info, prelude::*};
+use fedimint_core::config::FederationId;
 use fedimint_lnv2_client::FinalSendOperationState;
+use lightning_invoice::Bolt11Invoice;
 
-use crate::{load_multimint, FederationSelector};
+use crate::{
+    components::pin::ask_pin_if_required,
+    db::OperationAnnotation,
+    lnurl::{self, PayRequest},
+    parser::{parse_payment_string, PaymentString},
+    units::{format_amount, parse_amount},
+    FederationSelector, DISPLAY_UNIT, WALLET,
+};
 
 #[component]
-pub fn Send(federation_info: FederationSelector) -> Element {
-    let mut invoice = use_signal(|| String::new());
+pub fn Send(
+    federation_info: FederationSelector,
+    #[props(default)] initial_input: String,
+) -> Element {
+    let mut invoice = use_signal(|| initial_input);
     let sending = use_signal(|| false);
     let result = use_signal(|| None::<String>);
+    let pay_request = use_signal(|| None::<PayRequest>);
+    let mut amount_input = use_signal(|| String::new());
+    let mut lnurl_comment = use_signal(|| String::new());
+    let mut label = use_signal(|| String::new());
+
+    // Amountless invoices can't be paid, explain why as soon as one is entered
+    let amountless_reason = use_resource(move || async move {
+        let is_amountless = Bolt11Invoice::from_str(invoice().trim())
+            .is_ok_and(|invoice| invoice.amount_milli_satoshis().is_none());
+        if !is_amountless {
+            return None;
+        }
+        let reason = match WALLET().multimint().await {
+            Ok(mm) => mm.amountless_unsupported_reason(&federation_info.federation_id),
+            Err(e) => Err(e),
+        };
+        Some(reason.unwrap_or_else(|e| e.user_message(DISPLAY_UNIT())))
+    });
+
+    // Contacts that can be paid over Lightning, with the identifier to pay
+    let payable_contacts = use_resource(move || async move {
+        let Ok(mm) = WALLET().multimint().await else {
+            return Vec::new();
+        };
+        mm.contacts()
+            .await
+            .into_values()
+            .filter_map(|contact| {
+                let identifier = contact.identifiers.iter().find(|identifier| {
+                    matches!(
+                        parse_payment_string(identifier),
+                        Ok(PaymentString::LightningAddress(_) | PaymentString::Lnurl(_))
+                    )
+                })?;
+                Some((contact.name.clone(), identifier.clone()))
+            })
+            .collect::<Vec<_>>()
+    });
 
     let on_send = {
-        to_owned![invoice, sending, result];
+        to_owned![invoice, sending, result, pay_request];
 
         move |_| {
             let invoice_value = invoice().trim().to_string();
             if invoice_value.is_empty() {
                 result.set(Some("Invoice cannot be empty".to_string()));
+                return;
             }
 
             sending.set(true);
             result.set(None);
 
             spawn({
-                to_owned![sending, result];
+                to_owned![sending, result, pay_request];
                 async move {
-                    let multimint = load_multimint().await;
-                    let mm = multimint.read().await;
-                    if let Some(mm) = mm.as_ref() {
-                        match mm.send(&federation_info.federation_id, invoice_value).await {
-                            Ok(operation_id) => {
-                                result.set(Some(format!("Payment sent...")));
-
-                                match mm
-                                    .await_send(&federation_info.federation_id, operation_id)
-                                    .await
-                                {
-                                    Ok(FinalSendOperationState::Success) => {
-                                        result.set(Some(format!("Invoice paid successfully")));
-                                    }
-                                    Ok(_) => {
-                                        result.set(Some(format!("Error when paying invoice")));
-                                    }
-                                    _ => {
-                                        result.set(Some(format!("Unspecified error")));
-                                    }
-                                }
-
-                                sending.set(false);
-                            }
+                    if lnurl::is_lnurl(&invoice_value) {
+                        match lnurl::resolve_pay(&invoice_value).await {
+                            Ok(request) => pay_request.set(Some(request)),
                             Err(e) => {
-                                info!("Send returning error: {e}");
+                                info!("Could not resolve LNURL: {e}");
+                                result.set(Some(format!("Could not resolve LNURL: {e}")));
                             }
                         }
+                        sending.set(false);
+                        return;
                     }
+
+                    pay_invoice(
+                        federation_info.federation_id,
+                        invoice_value.clone(),
+                        invoice_value,
+                        label(),
+                        result,
+                    )
+                    .await;
+                    sending.set(false);
+                }
+            });
+        }
+    };
+
+    let on_pay_lnurl = {
+        to_owned![sending, result, pay_request];
+
+        move |_| {
+            let Some(request) = pay_request() else {
+                return;
+            };
+            let unit = DISPLAY_UNIT();
+            let amount = match parse_amount(&amount_input(), unit) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    result.set(Some(e.to_string()));
+                    return;
+                }
+            };
+            if amount < request.min_sendable() || amount > request.max_sendable() {
+                result.set(Some(format!(
+                    "Amount must be between {} and {}",
+                    format_amount(request.min_sendable(), unit),
+                    format_amount(request.max_sendable(), unit)
+                )));
+                return;
+            }
+            let comment = Some(lnurl_comment().trim().to_string());
+            let payee = invoice().trim().to_string();
+
+            sending.set(true);
+            result.set(None);
+
+            spawn({
+                to_owned![sending, result];
+                async move {
+                    match lnurl::fetch_invoice(&request, amount, comment).await {
+                        Ok(invoice) => {
+                            pay_invoice(
+                                federation_info.federation_id,
+                                payee,
+                                invoice.to_string(),
+                                label(),
+                                result,
+                            )
+                            .await;
+                        }
+                        Err(e) => {
+                            info!("Could not fetch LNURL invoice: {e}");
+                            result.set(Some(format!("Could not get invoice: {e}")));
+                        }
+                    }
+                    sending.set(false);
                 }
             });
         }
@@ -62,18 +161,98 @@ pub fn Send(federation_info: FederationSelector) -> Element {
         div {
             class: "invoice-container",
             h2 { class: "invoice-title", "Send Lightning Payment" }
+            if let Some(contacts) = payable_contacts().filter(|contacts| !contacts.is_empty()) {
+                select {
+                    class: "contact-select",
+                    onchange: {
+                        to_owned![pay_request];
+                        move |e: FormEvent| {
+                            if !e.value().is_empty() {
+                                invoice.set(e.value());
+                                pay_request.set(None);
+                            }
+                        }
+                    },
+                    option { value: "", "Pay a contact..." }
+                    for (name, identifier) in contacts {
+                        option { value: "{identifier}", "{name}" }
+                    }
+                }
+            }
             textarea {
                 class: "invoice-input",
                 rows: 4,
                 value: "{invoice}",
-                oninput: move |e| invoice.set(e.value().clone()),
-                placeholder: "Paste Lightning Invoice..."
+                oninput: {
+                    to_owned![pay_request];
+                    move |e: FormEvent| {
+                        invoice.set(e.value().clone());
+                        pay_request.set(None);
+                    }
+                },
+                placeholder: "Paste Lightning Invoice, LNURL or Lightning Address..."
+            }
+            input {
+                class: "invoice-input",
+                r#type: "text",
+                placeholder: "Label (only visible to you)",
+                value: "{label}",
+                oninput: move |e| label.set(e.value())
             }
-            button {
-                class: "invoice-button",
-                onclick: on_send,
-                disabled: "{sending()}",
-                "Send"
+
+            match pay_request() {
+                Some(request) => rsx! {
+                    div {
+                        class: "lnurl-details",
+                        if let Some(identifier) = request.identifier() {
+                            p { class: "lnurl-identifier", "{identifier}" }
+                        }
+                        if let Some(description) = request.description() {
+                            p { class: "lnurl-description", "{description}" }
+                        }
+                        p {
+                            class: "lnurl-range",
+                            "Min {format_amount(request.min_sendable(), DISPLAY_UNIT())}, max {format_amount(request.max_sendable(), DISPLAY_UNIT())}"
+                        }
+                    }
+                    input {
+                        class: "invoice-input",
+                        r#type: "text",
+                        inputmode: "decimal",
+                        placeholder: "{DISPLAY_UNIT().placeholder()}",
+                        value: "{amount_input}",
+                        oninput: move |e| amount_input.set(e.value().clone())
+                    }
+                    if request.comment_allowed > 0 {
+                        input {
+                            class: "invoice-input",
+                            r#type: "text",
+                            maxlength: "{request.comment_allowed}",
+                            placeholder: "Comment (optional)",
+                            value: "{lnurl_comment}",
+                            oninput: move |e| lnurl_comment.set(e.value().clone())
+                        }
+                    }
+                    button {
+                        class: "invoice-button",
+                        onclick: on_pay_lnurl,
+                        disabled: "{sending()}",
+                        "Pay"
+                    }
+                },
+                None => match amountless_reason().flatten() {
+                    Some(reason) => rsx! {
+                        p { class: "lnurl-range", "{reason}" }
+                    },
+                    None => rsx! {
+                        button {
+                            class: "invoice-button",
+                            onclick: on_send,
+                            disabled: "{sending()}",
+                            "Send"
+                        }
+                    },
+                },
             }
 
             if let Some(res) = result() {
@@ -85,3 +264,56 @@ pub fn Send(federation_info: FederationSelector) -> Element {
         }
     }
 }
+
+/// Pays `invoice`, which was obtained from what the user entered as `payee`,
+/// and labels the payment with `label` if it is not empty.
+async fn pay_invoice(
+    federation_id: FederationId,
+    payee: String,
+    invoice: String,
+    label: String,
+    mut result: Signal<Option<String>>,
+) {
+    let mm = match WALLET().multimint().await {
+        Ok(mm) => mm,
+        Err(e) => {
+            result.set(Some(e.user_message(DISPLAY_UNIT())));
+            return;
+        }
+    };
+    match mm.send(&federation_id, invoice).await {
+        Ok(operation_id) => {
+            WALLET().track_pending(federation_id).await;
+            mm.link_contact(&payee, operation_id).await;
+            if !label.trim().is_empty() {
+                mm.annotate(operation_id, &OperationAnnotation::with_label(&label))
+                    .await;
+            }
+            result.set(Some("Payment sent...".to_string()));
+
+            match mm.await_send(&federation_id, operation_id).await {
+                Ok(FinalSendOperationState::Success) => {
+                    result.set(Some("Invoice paid successfully".to_string()));
+                }
+                Ok(FinalSendOperationState::Refunded) => {
+                    result.set(Some(String::from(
+                        "The gateway could not route the payment, your funds were refunded",
+                    )));
+                }
+                Ok(_) => {
+                    result.set(Some(String::from(
+                        "Payment failed. Check your balance and try again later",
+                    )));
+                }
+                Err(e) => {
+                    result.set(Some(e.user_message(DISPLAY_UNIT())));
+                }
+            }
+        }
+        Err(e) => {
+            info!("Send returning error: {e}");
+            ask_pin_if_required(&e);
+            result.set(Some(e.user_message(DISPLAY_UNIT())));
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use std::str::FromStr;
+
+use dioxus::prelude::*;
+use fedimint_api_client::api::net::Connector;
+use fedimint_core::{config::FederationId, util::SafeUrl, Amount};
+
+use crate::{
+    components::pin::authenticate,
+    limits::{LimitKind, PinHash, PinPurpose, SpendingLimits},
+    nostr::DEFAULT_RELAYS,
+    price::{format_cents, parse_cents, PriceSource, SUPPORTED_CURRENCIES},
+    settings::{reset_settings, update_settings, CONNECTOR_CHOICES},
+    units::{format_number, parse_amount, AmountParseError, DisplayUnit},
+    DISPLAY_UNIT, FEDERATIONS, FIAT_CURRENCY, FIAT_RATE, SETTINGS, SETTINGS_ERROR,
+};
+
+/// Choices for the auto-lock timeout in minutes, `None` never locks.
+const AUTO_LOCK_CHOICES: [Option<u32>; 5] = [None, Some(1), Some(5), Some(15), Some(60)];
+
+/// Edits the user's settings. Every change is saved right away.
+#[component]
+pub fn Settings() -> Element {
+    let settings = SETTINGS();
+    let gateways = settings
+        .preferred_gateways
+        .iter()
+        .map(|gateway| gateway.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let default_federation = settings
+        .default_federation
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    let relays = settings.nostr_relays.join("\n");
+    let relay_placeholder = DEFAULT_RELAYS.join("\n");
+    let mut gateway_error = use_signal(|| None::<String>);
+    let mut unlocked = use_signal(|| false);
+    // Security settings and limits only change after the PIN was entered
+    let locked = settings.pin.is_some() && !unlocked();
+
+    rsx! {
+        div {
+            class: "screen",
+            h2 { "Settings" }
+            if let Some(msg) = SETTINGS_ERROR() {
+                p { class: "balance-error", "{msg}" }
+                button {
+                    class: "secondary-button",
+                    onclick: move |_| reset_settings(),
+                    "Reset settings"
+                }
+            }
+
+            h3 { "Display" }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "display-unit", "Unit" }
+                select {
+                    id: "display-unit",
+                    class: "settings-select",
+                    value: "{settings.display_unit.label()}",
+                    onchange: move |evt| {
+                        if let Some(unit) = DisplayUnit::from_label(&evt.value()) {
+                            update_settings(|settings| settings.display_unit = unit);
+                        }
+                    },
+                    for unit in DisplayUnit::ALL {
+                        option { value: "{unit.label()}", "{unit.label()}" }
+                    }
+                }
+            }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "fiat-currency", "Fiat" }
+                select {
+                    id: "fiat-currency",
+                    class: "settings-select",
+                    value: "{settings.fiat_currency.clone().unwrap_or_default()}",
+                    onchange: move |evt| {
+                        let currency = evt.value();
+                        update_settings(|settings| {
+                            settings.fiat_currency = (!currency.is_empty()).then_some(currency)
+                        });
+                    },
+                    option { value: "", "Off" }
+                    for currency in SUPPORTED_CURRENCIES {
+                        option { value: "{currency}", "{currency}" }
+                    }
+                }
+            }
+            if let Some(currency) = FIAT_CURRENCY() {
+                PriceSourceSetting { currency }
+            }
+
+            h3 { "Payments" }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "default-federation", "Default federation" }
+                select {
+                    id: "default-federation",
+                    class: "settings-select",
+                    value: "{default_federation}",
+                    onchange: move |evt| {
+                        let federation = FederationId::from_str(&evt.value()).ok();
+                        update_settings(|settings| settings.default_federation = federation);
+                    },
+                    option { value: "", "None" }
+                    for item in FEDERATIONS().unwrap_or_default() {
+                        option { value: "{item.federation_id}", "{item}" }
+                    }
+                }
+            }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "preferred-gateways", "Preferred gateways" }
+                textarea {
+                    id: "preferred-gateways",
+                    class: "settings-textarea",
+                    placeholder: "One gateway URL per line",
+                    value: "{gateways}",
+                    onchange: move |evt| {
+                        let parsed = evt
+                            .value()
+                            .lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty())
+                            .map(SafeUrl::parse)
+                            .collect::<Result<Vec<_>, _>>();
+                        match parsed {
+                            Ok(gateways) => {
+                                gateway_error.set(None);
+                                update_settings(|settings| settings.preferred_gateways = gateways);
+                            }
+                            Err(e) => gateway_error.set(Some(format!("Invalid gateway URL: {e}"))),
+                        }
+                    }
+                }
+            }
+            if let Some(msg) = gateway_error() {
+                p { class: "balance-error", "{msg}" }
+            }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "connector", "Connect via" }
+                select {
+                    id: "connector",
+                    class: "settings-select",
+                    onchange: move |evt| {
+                        if let Ok(connector) = Connector::from_str(&evt.value()) {
+                            update_settings(|settings| settings.connector = connector);
+                        }
+                    },
+                    for choice in CONNECTOR_CHOICES {
+                        if let Ok(connector) = Connector::from_str(choice) {
+                            option {
+                                value: "{choice}",
+                                selected: connector == settings.connector,
+                                "{choice}"
+                            }
+                        }
+                    }
+                }
+            }
+            p { class: "screen-hint", "Used for federations joined from now on." }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "nostr-relays", "Nostr relays" }
+                textarea {
+                    id: "nostr-relays",
+                    class: "settings-textarea",
+                    placeholder: "{relay_placeholder}",
+                    value: "{relays}",
+                    onchange: move |evt| {
+                        let relays = evt
+                            .value()
+                            .lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty())
+                            .map(str::to_owned)
+                            .collect::<Vec<_>>();
+                        update_settings(|settings| settings.nostr_relays = relays);
+                    }
+                }
+            }
+            p { class: "screen-hint", "Searched for federations to join, the ones shown are used if empty." }
+
+            h3 { "Notifications" }
+            label {
+                class: "settings-check",
+                input {
+                    r#type: "checkbox",
+                    checked: settings.notifications.payments_received,
+                    onchange: move |evt| {
+                        let checked = evt.checked();
+                        update_settings(|settings| settings.notifications.payments_received = checked);
+                    }
+                }
+                "Payments received"
+            }
+            label {
+                class: "settings-check",
+                input {
+                    r#type: "checkbox",
+                    checked: settings.notifications.payments_failed,
+                    onchange: move |evt| {
+                        let checked = evt.checked();
+                        update_settings(|settings| settings.notifications.payments_failed = checked);
+                    }
+                }
+                "Failed or refunded payments"
+            }
+            label {
+                class: "settings-check",
+                input {
+                    r#type: "checkbox",
+                    checked: settings.notifications.onchain,
+                    onchange: move |evt| {
+                        let checked = evt.checked();
+                        update_settings(|settings| settings.notifications.onchain = checked);
+                    }
+                }
+                "On-chain deposits and withdrawals"
+            }
+
+            h3 { "Security" }
+            if locked {
+                SettingsUnlock { on_unlock: move |_| unlocked.set(true) }
+            }
+            PinSetting { disabled: locked }
+            div {
+                class: "settings-row",
+                label { class: "settings-label", r#for: "auto-lock", "Auto-lock" }
+                select {
+                    id: "auto-lock",
+                    class: "settings-select",
+                    // Locking needs a PIN to unlock with
+                    disabled: locked || settings.pin.is_none(),
+                    onchange: move |evt| {
+                        let minutes = evt.value().parse::<u32>().ok();
+                        update_settings(|settings| settings.auto_lock_minutes = minutes);
+                    },
+                    for choice in AUTO_LOCK_CHOICES {
+                        option {
+                            value: choice.map(|minutes| minutes.to_string()).unwrap_or_default(),
+                            selected: choice == settings.auto_lock_minutes,
+                            if let Some(minutes) = choice {
+                                "After {minutes} min"
+                            } else {
+                                "Never"
+                            }
+                        }
+                    }
+                }
+            }
+            AmountSetting {
+                id: "confirm-above",
+                label: "Ask for PIN above",
+                placeholder: "Never",
+                value: settings.confirm_above,
+                disabled: locked,
+                on_change: move |amount| update_settings(|settings| settings.confirm_above = amount)
+            }
+            if settings.pin.is_none() {
+                p { class: "screen-hint", "Set a PIN to use auto-lock and to confirm large payments." }
+            }
+
+            h3 { "Spending limits" }
+            p {
+                class: "screen-hint",
+                "Daily and weekly limits count the payments of the last 24 hours and 7 days. Leave a limit empty to turn it off."
+            }
+            h4 { "All federations" }
+            LimitsEditor {
+                id: "all",
+                limits: settings.limits.clone(),
+                disabled: locked,
+                on_change: move |limits| update_settings(|settings| settings.limits = limits)
+            }
+            for item in FEDERATIONS().unwrap_or_default() {
+                details {
+                    key: "{item.federation_id}",
+                    class: "settings-group",
+                    summary { "{item}" }
+                    LimitsEditor {
+                        id: "{item.federation_id}",
+                        limits: settings
+                            .federation_limits
+                            .get(&item.federation_id)
+                            .cloned()
+                            .unwrap_or_default(),
+                        disabled: locked,
+                        on_change: move |limits: SpendingLimits| {
+                            update_settings(|settings| {
+                                if limits.is_empty() {
+                                    settings.federation_limits.remove(&item.federation_id);
+                                } else {
+                                    settings.federation_limits.insert(item.federation_id, limits);
+                                }
+                            })
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Chooses between quoting prices from a JSON HTTP endpoint and a fixed rate
+/// entered by the user.
+#[component]
+fn PriceSourceSetting(currency: String) -> Element {
+    let source = SETTINGS.read().price_source.clone();
+    let mut error = use_signal(|| None::<String>);
+
+    rsx! {
+        div {
+            class: "settings-row",
+            label { class: "settings-label", r#for: "price-source", "Price source" }
+            select {
+                id: "price-source",
+                class: "settings-select",
+                onchange: move |evt| {
+                    let source = match evt.value().as_str() {
+                        "static" => PriceSource::Static {
+                            cents_per_btc: FIAT_RATE()
+                                .map(|rate| rate.cents_per_btc)
+                                .unwrap_or_default(),
+                        },
+                        _ => PriceSource::default(),
+                    };
+                    error.set(None);
+                    update_settings(|settings| settings.price_source = source);
+                },
+                option {
+                    value: "http",
+                    selected: matches!(source, PriceSource::Http { .. }),
+                    "Web API"
+                }
+                option {
+                    value: "static",
+                    selected: matches!(source, PriceSource::Static { .. }),
+                    "Fixed rate"
+                }
+            }
+        }
+        match source {
+            PriceSource::Http { url, pointer } => rsx! {
+                div {
+                    class: "settings-row",
+                    label { class: "settings-label", r#for: "price-url", "URL" }
+                    input {
+                        id: "price-url",
+                        class: "settings-input",
+                        r#type: "url",
+                        value: "{url}",
+                        onchange: move |evt| {
+                            let value = evt.value();
+                            update_settings(|settings| {
+                                if let PriceSource::Http { url, .. } = &mut settings.price_source {
+                                    *url = value;
+                                }
+                            });
+                        }
+                    }
+                }
+                div {
+                    class: "settings-row",
+                    label { class: "settings-label", r#for: "price-pointer", "JSON pointer" }
+                    input {
+                        id: "price-pointer",
+                        class: "settings-input",
+                        r#type: "text",
+                        value: "{pointer}",
+                        onchange: move |evt| {
+                            let value = evt.value();
+                            update_settings(|settings| {
+                                if let PriceSource::Http { pointer, .. } = &mut settings.price_source {
+                                    *pointer = value;
+                                }
+                            });
+                        }
+                    }
+                }
+                p {
+                    class: "screen-hint",
+                    "{{currency}} and {{CURRENCY}} in either are replaced with the currency code."
+                }
+            },
+            PriceSource::Static { cents_per_btc } => {
+                let rate = (cents_per_btc > 0)
+                    .then(|| format_cents(cents_per_btc, &currency))
+                    .unwrap_or_default();
+                rsx! {
+                    div {
+                        class: "settings-row",
+                        label { class: "settings-label", r#for: "price-rate", "1 BTC =" }
+                        input {
+                            id: "price-rate",
+                            class: "settings-input",
+                            r#type: "text",
+                            inputmode: "decimal",
+                            value: "{rate}",
+                            onchange: {
+                                to_owned![currency];
+                                move |evt: FormEvent| match parse_cents(&evt.value(), &currency) {
+                                    Ok(cents_per_btc) if cents_per_btc > 0 => {
+                                        error.set(None);
+                                        update_settings(|settings| {
+                                            settings.price_source = PriceSource::Static { cents_per_btc }
+                                        });
+                                    }
+                                    Ok(_) => error.set(Some("Enter a rate greater than zero".to_string())),
+                                    Err(e) => error.set(Some(e.to_string())),
+                                }
+                            }
+                        }
+                        span { class: "settings-unit", "{currency}" }
+                    }
+                }
+            }
+        }
+        if let Some(msg) = error() {
+            p { class: "balance-error", "{msg}" }
+        }
+    }
+}
+
+/// Asks for the current PIN before security settings and limits can be
+/// changed.
+#[component]
+fn SettingsUnlock(on_unlock: EventHandler) -> Element {
+    let mut pin = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut checking = use_signal(|| false);
+
+    let mut on_submit = move || {
+        checking.set(true);
+        spawn(async move {
+            match authenticate(pin(), PinPurpose::ChangeSettings).await {
+                Ok(()) => {
+                    error.set(None);
+                    on_unlock.call(());
+                }
+                Err(e) => error.set(Some(e.user_message(DISPLAY_UNIT()))),
+            }
+            pin.set(String::new());
+            checking.set(false);
+        });
+    };
+
+    rsx! {
+        div {
+            class: "settings-row",
+            label { class: "settings-label", r#for: "current-pin", "Current PIN" }
+            input {
+                id: "current-pin",
+                class: "settings-input",
+                r#type: "password",
+                inputmode: "numeric",
+                value: "{pin}",
+                oninput: move |evt| pin.set(evt.value()),
+                onkeydown: move |evt| {
+                    if evt.key() == Key::Enter {
+                        on_submit();
+                    }
+                }
+            }
+            button {
+                class: "secondary-button",
+                disabled: checking() || pin().is_empty(),
+                onclick: move |_| on_submit(),
+                "Unlock"
+            }
+        }
+        p { class: "screen-hint", "Enter your PIN to change the settings below." }
+        if let Some(msg) = error() {
+            p { class: "balance-error", "{msg}" }
+        }
+    }
+}
+
+/// Sets, changes or removes the PIN. Disabled until the current PIN was
+/// entered, see `SettingsUnlock`.
+#[component]
+fn PinSetting(disabled: bool) -> Element {
+    let has_pin = SETTINGS.read().pin.is_some();
+    let mut new_pin = use_signal(String::new);
+    let mut message = use_signal(|| None::<String>);
+
+    let mut save = move |pin: Option<String>| {
+        if pin.as_deref().is_some_and(|pin| !PinHash::is_valid_pin(pin)) {
+            message.set(Some("A PIN is 4 to 12 digits.".to_string()));
+            return;
+        }
+        let saved = if pin.is_some() { "PIN saved." } else { "PIN removed." };
+        update_settings(|settings| settings.pin = pin.as_deref().map(PinHash::new));
+        new_pin.set(String::new());
+        message.set(Some(saved.to_string()));
+    };
+
+    rsx! {
+        div {
+            class: "settings-row",
+            label { class: "settings-label", r#for: "new-pin", "PIN" }
+            input {
+                id: "new-pin",
+                class: "settings-input",
+                disabled,
+                r#type: "password",
+                inputmode: "numeric",
+                placeholder: if has_pin { "New PIN" } else { "4 to 12 digits" },
+                value: "{new_pin}",
+                oninput: move |evt| new_pin.set(evt.value())
+            }
+            button {
+                class: "secondary-button",
+                disabled,
+                onclick: move |_| save(Some(new_pin())),
+                if has_pin { "Change" } else { "Set" }
+            }
+            if has_pin {
+                button {
+                    class: "secondary-button",
+                    disabled,
+                    onclick: move |_| save(None),
+                    "Remove"
+                }
+            }
+        }
+        if let Some(msg) = message() {
+            p { class: "screen-hint", "{msg}" }
+        }
+    }
+}
+
+/// Edits one set of spending limits.
+#[component]
+fn LimitsEditor(
+    id: String,
+    limits: SpendingLimits,
+    disabled: bool,
+    on_change: EventHandler<SpendingLimits>,
+) -> Element {
+    rsx! {
+        for kind in LimitKind::ALL {
+            AmountSetting {
+                key: "{kind:?}",
+                id: "{id}-{kind:?}",
+                label: kind.label(),
+                placeholder: "No limit",
+                value: limits.get(kind),
+                disabled,
+                on_change: {
+                    let limits = limits.clone();
+                    move |limit| {
+                        let mut limits = limits.clone();
+                        limits.set(kind, limit);
+                        on_change.call(limits);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An optional amount in the display unit, saved when the input loses focus.
+/// Clearing the input sets it to `None`.
+#[component]
+fn AmountSetting(
+    id: String,
+    label: &'static str,
+    placeholder: &'static str,
+    value: Option<Amount>,
+    disabled: bool,
+    on_change: EventHandler<Option<Amount>>,
+) -> Element {
+    let unit = DISPLAY_UNIT();
+    let formatted = value
+        .map(|amount| format_number(amount, unit))
+        .unwrap_or_default();
+    let mut error = use_signal(|| None::<String>);
+
+    rsx! {
+        div {
+            class: "settings-row",
+            label { class: "settings-label", r#for: "{id}", "{label}" }
+            input {
+                id: "{id}",
+                class: "settings-input",
+                r#type: "text",
+                inputmode: "decimal",
+                placeholder: "{placeholder}",
+                disabled,
+                value: "{formatted}",
+                onchange: move |evt| {
+                    let amount = match parse_amount(&evt.value(), unit) {
+                        Ok(amount) => Some(amount),
+                        Err(AmountParseError::Empty) => None,
+                        Err(e) => {
+                            error.set(Some(e.to_string()));
+                            return;
+                        }
+                    };
+                    error.set(None);
+                    on_change.call(amount);
+                }
+            }
+            span { class: "settings-unit", "{unit}" }
+        }
+        if let Some(msg) = error() {
+            p { class: "balance-error", "{msg}" }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use dioxus::{logger::tracing::info, prelude::*};
+use fedimint_core::Amount;
+
+use crate::{
+    components::{pin::ask_pin_if_required, qr::QrDisplay},
+    units::{format_amount, parse_amount},
+    FederationSelector, DISPLAY_UNIT, WALLET,
+};
+
+#[component]
+pub fn SpendEcash(federation_info: FederationSelector) -> Element {
+    let mut amount_input = use_signal(|| String::new());
+    // The notes and the amount they requested, which note selection may exceed
+    let mut notes = use_signal(|| None::<(String, Amount, Amount)>);
+    let spending = use_signal(|| false);
+    let result = use_signal(|| None::<String>);
+
+    let on_spend = {
+        to_owned![spending, result];
+
+        move |_| {
+            let amount = match parse_amount(&amount_input(), DISPLAY_UNIT()) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    result.set(Some(e.to_string()));
+                    return;
+                }
+            };
+
+            spending.set(true);
+            result.set(None);
+
+            spawn({
+                to_owned![spending, result];
+                async move {
+                    match WALLET().multimint().await {
+                        Ok(mm) => {
+                            match mm.spend_ecash(&federation_info.federation_id, amount).await {
+                                Ok((_, oob_notes)) => {
+                                    WALLET().track_pending(federation_info.federation_id).await;
+                                    notes.set(Some((
+                                        oob_notes.to_string(),
+                                        amount,
+                                        oob_notes.total_amount(),
+                                    )));
+                                }
+                                Err(e) => {
+                                    info!("Spend returning error: {e}");
+                                    ask_pin_if_required(&e);
+                                    result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                }
+                            }
+                        }
+                        Err(e) => result.set(Some(e.user_message(DISPLAY_UNIT()))),
+                    }
+                    spending.set(false);
+                }
+            });
+        }
+    };
+
+    rsx! {
+        div {
+            class: "invoice-container",
+            h2 { class: "invoice-title", "Spend Ecash" }
+
+            match notes() {
+                Some((notes, requested, total)) => rsx! {
+                    p {
+                        class: "lnurl-range",
+                        "Hand these notes worth {format_amount(total, DISPLAY_UNIT())} to the recipient"
+                    }
+                    if total > requested {
+                        p {
+                            class: "screen-hint",
+                            "Your notes could not make up {format_amount(requested, DISPLAY_UNIT())} exactly, so the recipient gets a little more."
+                        }
+                    }
+                    QrDisplay { value: notes }
+                },
+                None => rsx! {
+                    input {
+                        class: "invoice-input",
+                        r#type: "text",
+                        inputmode: "decimal",
+                        placeholder: "{DISPLAY_UNIT().placeholder()}",
+                        value: "{amount_input}",
+                        oninput: move |e| amount_input.set(e.value().clone())
+                    }
+                    button {
+                        class: "invoice-button",
+                        onclick: on_spend,
+                        disabled: "{spending()}",
+                        "Create Notes"
+                    }
+                }
+            }
+
+            if let Some(res) = result() {
+                div {
+                    class: "invoice-output",
+                    "{res}"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::str::FromStr;
+
+use dioxus::{logger::tracing::info, prelude::*};
+use fedimint_core::{
+    bitcoin::{self, address::NetworkUnchecked, Address},
+    Amount,
+};
+use fedimint_wallet_client::PegOutFees;
+
+use crate::{
+    components::pin::ask_pin_if_required,
+    units::{format_amount, parse_amount},
+    FederationSelector, DISPLAY_UNIT, WALLET,
+};
+
+#[component]
+pub fn Withdraw(
+    federation_info: FederationSelector,
+    #[props(default)] initial_address: String,
+    #[props(default)] initial_amount: String,
+) -> Element {
+    let mut address = use_signal(|| initial_address);
+    let mut amount_input = use_signal(|| initial_amount);
+    let mut fees = use_signal(|| None::<PegOutFees>);
+    let withdrawing = use_signal(|| false);
+    let result = use_signal(|| None::<String>);
+
+    let parse_inputs = move || -> Result<(Address<NetworkUnchecked>, bitcoin::Amount), String> {
+        let address = Address::from_str(address().trim()).map_err(|_| "Invalid address")?;
+        let amount = parse_amount(&amount_input(), DISPLAY_UNIT()).map_err(|e| e.to_string())?;
+        if amount.msats % 1000 != 0 {
+            return Err("On-chain amounts must be whole sats".to_string());
+        }
+        Ok((address, bitcoin::Amount::from_sat(amount.msats / 1000)))
+    };
+
+    let on_quote = {
+        to_owned![withdrawing, result];
+
+        move |_| {
+            let (address, amount) = match parse_inputs() {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    result.set(Some(e));
+                    return;
+                }
+            };
+
+            withdrawing.set(true);
+            result.set(None);
+
+            spawn({
+                to_owned![withdrawing, result];
+                async move {
+                    match WALLET().multimint().await {
+                        Ok(mm) => {
+                            match mm
+                                .withdraw_fees(&federation_info.federation_id, address, amount)
+                                .await
+                            {
+                                Ok(quote) => fees.set(Some(quote)),
+                                Err(e) => {
+                                    info!("Could not get withdraw fees: {e}");
+                                    result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                }
+                            }
+                        }
+                        Err(e) => result.set(Some(e.user_message(DISPLAY_UNIT()))),
+                    }
+                    withdrawing.set(false);
+                }
+            });
+        }
+    };
+
+    let on_withdraw = {
+        to_owned![withdrawing, result];
+
+        move |_| {
+            let (Ok((address, amount)), Some(quote)) = (parse_inputs(), fees()) else {
+                return;
+            };
+
+            withdrawing.set(true);
+            result.set(None);
+
+            spawn({
+                to_owned![withdrawing, result];
+                async move {
+                    match WALLET().multimint().await {
+                        Ok(mm) => {
+                            match mm
+                                .withdraw(&federation_info.federation_id, address, amount, quote)
+                                .await
+                            {
+                                Ok(operation_id) => {
+                                    WALLET().track_pending(federation_info.federation_id).await;
+                                    result.set(Some("Withdrawal submitted...".to_string()));
+
+                                    match mm
+                                        .await_withdraw(
+                                            &federation_info.federation_id,
+                                            operation_id,
+                                        )
+                                        .await
+                                    {
+                                        Ok(txid) => {
+                                            result.set(Some(format!("Withdrawal broadcast: {txid}")));
+                                        }
+                                        Err(e) => {
+                                            result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    info!("Withdraw returning error: {e}");
+                                    ask_pin_if_required(&e);
+                                    result.set(Some(e.user_message(DISPLAY_UNIT())));
+                                }
+                            }
+                        }
+                        Err(e) => result.set(Some(e.user_message(DISPLAY_UNIT()))),
+                    }
+                    fees.set(None);
+                    withdrawing.set(false);
+                }
+            });
+        }
+    };
+
+    rsx! {
+        div {
+            class: "invoice-container",
+            h2 { class: "invoice-title", "Withdraw On-chain" }
+            input {
+                class: "invoice-input",
+                r#type: "text",
+                placeholder: "Bitcoin address",
+                value: "{address}",
+                oninput: move |e| {
+                    address.set(e.value().clone());
+                    fees.set(None);
+                }
+            }
+            input {
+                class: "invoice-input",
+                r#type: "text",
+                inputmode: "decimal",
+                placeholder: "{DISPLAY_UNIT().placeholder()}",
+                value: "{amount_input}",
+                oninput: move |e| {
+                    amount_input.set(e.value().clone());
+                    fees.set(None);
+                }
+            }
+
+            match fees() {
+                Some(quote) => rsx! {
+                    p {
+                        class: "lnurl-range",
+                        "Network fee: {format_amount(Amount::from_sats(quote.amount().to_sat()), DISPLAY_UNIT())}"
+                    }
+                    button {
+                        class: "invoice-button",
+                        onclick: on_withdraw,
+                        disabled: "{withdrawing()}",
+                        "Confirm Withdrawal"
+                    }
+                },
+                None => rsx! {
+                    button {
+                        class: "invoice-button",
+                        onclick: on_quote,
+                        disabled: "{withdrawing()}",
+                        "Get Fee Quote"
+                    }
+                }
+            }
+
+            if let Some(res) = result() {
+                div {
+                    class: "invoice-output",
+                    "{res}"
+                }
+            }
+        }
+    }
+}
@@ -1,17 +1,28 @@
 use fedimint_api_client::api::net::Connector;
 use fedimint_core::{
     config::FederationId,
+    core::OperationId,
     encoding::{Decodable, Encodable},
     impl_db_lookup, impl_db_record,
     invite_code::InviteCode,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::meta::FederationMeta;
+
 #[repr(u8)]
 #[derive(Clone, Debug)]
 pub(crate) enum DbKeyPrefix {
     FederationConfig = 0x00,
     ClientDatabase = 0x01,
+    FiatPrice = 0x02,
+    Settings = 0x03,
+    SchemaVersion = 0x04,
+    Contact = 0x05,
+    OperationContact = 0x06,
+    OperationAnnotation = 0x07,
+    PinAttempts = 0x08,
+    NextContactId = 0x09,
 }
 
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -24,6 +35,8 @@ pub(crate) struct FederationConfig {
     pub invite_code: InviteCode,
     pub connector: Connector,
     pub federation_name: String,
+    /// Last meta read from the federation, refreshed whenever its client loads.
+    pub meta: FederationMeta,
 }
 
 #[derive(Debug, Encodable, Decodable)]
@@ -39,3 +52,223 @@ impl_db_lookup!(
     key = FederationConfigKey,
     query_prefix = FederationConfigKeyPrefix
 );
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct FederationConfigKeyV0 {
+    pub(crate) id: FederationId,
+}
+
+/// `FederationConfig` as stored up to schema version 1, before the meta was
+/// cached. Only read by `migrations`.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct FederationConfigV0 {
+    pub invite_code: InviteCode,
+    pub connector: Connector,
+    pub federation_name: String,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub(crate) struct FederationConfigKeyPrefixV0;
+
+impl_db_record!(
+    key = FederationConfigKeyV0,
+    value = FederationConfigV0,
+    db_prefix = DbKeyPrefix::FederationConfig,
+);
+
+impl_db_lookup!(
+    key = FederationConfigKeyV0,
+    query_prefix = FederationConfigKeyPrefixV0
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct FiatPriceKey {
+    pub(crate) currency: String,
+}
+
+/// Last bitcoin price we fetched for a currency, used when the price source
+/// is unreachable.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub(crate) struct FiatPrice {
+    pub cents_per_btc: u64,
+    pub fetched_at: u64,
+}
+
+impl_db_record!(
+    key = FiatPriceKey,
+    value = FiatPrice,
+    db_prefix = DbKeyPrefix::FiatPrice,
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct SettingsKey;
+
+/// The user's `Settings`, stored as JSON so fields can be added with a
+/// default without breaking older records.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct SettingsRecord {
+    pub json: String,
+}
+
+impl_db_record!(
+    key = SettingsKey,
+    value = SettingsRecord,
+    db_prefix = DbKeyPrefix::Settings,
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct SchemaVersionKey;
+
+/// Layout version of the records above, see `migrations`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct SchemaVersion(pub u64);
+
+impl_db_record!(
+    key = SchemaVersionKey,
+    value = SchemaVersion,
+    db_prefix = DbKeyPrefix::SchemaVersion,
+);
+
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct ContactKey {
+    pub(crate) id: u64,
+}
+
+/// Someone we pay regularly.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct Contact {
+    pub name: String,
+    pub avatar_url: Option<String>,
+    /// Lightning Addresses, LNURLs and nostr pubkeys of the contact.
+    pub identifiers: Vec<String>,
+    pub notes: String,
+}
+
+impl Contact {
+    /// Whether `payee`, as entered in the Send form, is one of the contact's
+    /// identifiers.
+    pub fn pays_to(&self, payee: &str) -> bool {
+        let normalize = |identifier: &str| {
+            let identifier = identifier.trim().to_lowercase();
+            identifier
+                .strip_prefix("lightning:")
+                .map(str::to_owned)
+                .unwrap_or(identifier)
+        };
+        let payee = normalize(payee);
+        self.identifiers
+            .iter()
+            .any(|identifier| normalize(identifier) == payee)
+    }
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub(crate) struct ContactKeyPrefix;
+
+impl_db_record!(
+    key = ContactKey,
+    value = Contact,
+    db_prefix = DbKeyPrefix::Contact,
+);
+
+impl_db_lookup!(key = ContactKey, query_prefix = ContactKeyPrefix);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct NextContactIdKey;
+
+// Id the next contact gets. It only ever increases, so operations linked to
+// a deleted contact are never shown under a new one.
+impl_db_record!(
+    key = NextContactIdKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::NextContactId,
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct OperationContactKey {
+    pub(crate) operation_id: OperationId,
+}
+
+// The contact an operation was made with. Kept when the contact is deleted,
+// the operation then simply has no contact anymore, see `NextContactIdKey`.
+impl_db_record!(
+    key = OperationContactKey,
+    value = ContactKey,
+    db_prefix = DbKeyPrefix::OperationContact,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+pub(crate) struct OperationContactKeyPrefix;
+
+impl_db_lookup!(key = OperationContactKey, query_prefix = OperationContactKeyPrefix);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct OperationAnnotationKey {
+    pub(crate) operation_id: OperationId,
+}
+
+/// What the user wrote down about an operation, since operation ids say
+/// nothing about what a payment was for.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct OperationAnnotation {
+    pub label: String,
+    pub note: String,
+    pub tags: Vec<String>,
+}
+
+impl OperationAnnotation {
+    pub fn with_label(label: &str) -> Self {
+        Self {
+            label: label.trim().to_owned(),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.label.is_empty() && self.note.is_empty() && self.tags.is_empty()
+    }
+
+    /// Parses comma separated tags, dropping empty and duplicate ones.
+    pub fn parse_tags(input: &str) -> Vec<String> {
+        let mut tags = Vec::<String>::new();
+        for tag in input.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_owned());
+            }
+        }
+        tags
+    }
+
+    /// Whether the label, note or a tag contains `query`, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [&self.label, &self.note]
+            .into_iter()
+            .chain(&self.tags)
+            .any(|text| text.to_lowercase().contains(&query))
+    }
+}
+
+impl_db_record!(
+    key = OperationAnnotationKey,
+    value = OperationAnnotation,
+    db_prefix = DbKeyPrefix::OperationAnnotation,
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct PinAttemptsKey;
+
+/// Wrong PINs entered in a row, kept so restarting carbine does not skip the
+/// delay before the next attempt.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Encodable, Decodable)]
+pub(crate) struct PinAttempts {
+    pub failures: u32,
+    /// Unix timestamp of the last wrong PIN.
+    pub last_failure: u64,
+}
+
+impl_db_record!(
+    key = PinAttemptsKey,
+    value = PinAttempts,
+    db_prefix = DbKeyPrefix::PinAttempts,
+);
@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use fedimint_core::{config::FederationId, Amount};
+use thiserror::Error;
+
+use crate::{
+    limits::{LimitKind, LimitScope},
+    multimint::{FederationStatus, LoadFailure},
+    units::{format_amount, DisplayUnit},
+};
+
+/// Errors returned by `Multimint`. The `Display` impl is meant for logs, use
+/// `user_message` for text shown in the UI.
+#[derive(Debug, Error)]
+pub(crate) enum CarbineError {
+    #[error("Unknown federation {0}")]
+    UnknownFederation(FederationId),
+    #[error("Federation is not available: {0:?}")]
+    FederationUnavailable(FederationStatus),
+    #[error("Federation {0} has already been joined")]
+    AlreadyJoined(FederationId),
+    #[error("Already joining federation {0}")]
+    JoinInProgress(FederationId),
+    #[error("Invalid invite code: {0}")]
+    InvalidInviteCode(String),
+    #[error("Federation config has no federation name")]
+    MissingFederationName,
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Insufficient funds: needed {needed}, available {available}")]
+    InsufficientFunds { needed: Amount, available: Amount },
+    #[error("{kind:?} {scope:?} limit of {limit} exceeded, {remaining} remaining")]
+    LimitExceeded {
+        kind: LimitKind,
+        scope: LimitScope,
+        limit: Amount,
+        remaining: Amount,
+    },
+    #[error("Payment of {0} needs to be confirmed with the PIN")]
+    ConfirmationRequired(Amount),
+    #[error("Wrong PIN")]
+    WrongPin,
+    #[error("Too many wrong PINs, next attempt in {0:?}")]
+    PinDelayed(Duration),
+    #[error("Settings can not be read: {0}")]
+    SettingsUnreadable(String),
+    #[error("Federation has no {0} module")]
+    ModuleMissing(&'static str),
+    #[error("Gateway error: {0}")]
+    Gateway(String),
+    #[error("Federation has no vetted Lightning gateway")]
+    NoGateway,
+    #[error("Invoice was already paid or a payment is pending")]
+    AlreadyPaid,
+    #[error("Amount is too small to receive over Lightning")]
+    AmountTooSmall,
+    #[error("Invalid invoice: {0}")]
+    InvalidInvoice(String),
+    #[error("Amountless invoice: {0}")]
+    AmountlessInvoice(String),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("Operation failed: {0}")]
+    OperationFailed(String),
+    #[error("Client error: {0}")]
+    Client(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Database schema version {found} is newer than supported version {supported}")]
+    UnsupportedSchema { found: u64, supported: u64 },
+    #[error("Wallet service unavailable: {0}")]
+    ServiceUnavailable(String),
+}
+
+impl CarbineError {
+    /// Explains the error to the user and, where possible, what to do about it.
+    pub fn user_message(&self, unit: DisplayUnit) -> String {
+        match self {
+            CarbineError::UnknownFederation(_) => {
+                "This federation is not loaded. Restart carbine and try again.".to_string()
+            }
+            CarbineError::FederationUnavailable(status) => match status {
+                FederationStatus::Failed(LoadFailure::Offline(_)) => {
+                    "This federation's guardians can not be reached right now, carbine keeps \
+                     trying. Check your connection."
+                        .to_string()
+                }
+                FederationStatus::Failed(failure) => {
+                    format!("This federation could not be loaded: {}", failure.reason())
+                }
+                FederationStatus::Recovering { .. } => {
+                    "This federation is still recovering your funds, try again when it is done."
+                        .to_string()
+                }
+                _ => "This federation is still loading, try again in a moment.".to_string(),
+            },
+            CarbineError::AlreadyJoined(_) => {
+                "You have already joined this federation.".to_string()
+            }
+            CarbineError::JoinInProgress(_) => {
+                "Already joining this federation, wait for it to finish.".to_string()
+            }
+            CarbineError::InvalidInviteCode(_) => {
+                "This is not a valid invite code. Check that you copied all of it.".to_string()
+            }
+            CarbineError::MissingFederationName => {
+                "The federation did not publish a name, ask its guardians to set one.".to_string()
+            }
+            CarbineError::Network(_) => {
+                "Could not reach the federation. Check your connection and try again.".to_string()
+            }
+            CarbineError::InsufficientFunds { needed, available } => format!(
+                "Not enough funds: this needs {} but your balance is {}.",
+                format_amount(*needed, unit),
+                format_amount(*available, unit)
+            ),
+            CarbineError::LimitExceeded {
+                kind,
+                scope,
+                limit,
+                remaining,
+            } => {
+                let scope = match scope {
+                    LimitScope::Federation => "for this federation",
+                    LimitScope::Global => "across your federations",
+                };
+                match kind {
+                    LimitKind::PerPayment => format!(
+                        "This payment is above your limit of {} per payment {scope}.",
+                        format_amount(*limit, unit)
+                    ),
+                    LimitKind::Daily | LimitKind::Weekly => format!(
+                        "This payment would exceed your {} limit of {} {scope}, only {} is left.",
+                        kind.label().to_lowercase(),
+                        format_amount(*limit, unit),
+                        format_amount(*remaining, unit)
+                    ),
+                }
+            }
+            CarbineError::ConfirmationRequired(_) => {
+                "Payments this large need your PIN. Enter it, then try again.".to_string()
+            }
+            CarbineError::WrongPin => "Wrong PIN, try again.".to_string(),
+            CarbineError::PinDelayed(delay) => {
+                let secs = delay.as_secs().max(1);
+                if secs < 120 {
+                    format!("Too many wrong PINs, try again in {secs} seconds.")
+                } else {
+                    format!("Too many wrong PINs, try again in {} minutes.", secs.div_ceil(60))
+                }
+            }
+            CarbineError::SettingsUnreadable(e) => format!(
+                "Your settings could not be read ({e}). Payments are paused until you reset \
+                 them in Settings."
+            ),
+            CarbineError::ModuleMissing(module) => {
+                format!("This federation does not support {module}.")
+            }
+            CarbineError::Gateway(e) => format!(
+                "The Lightning gateway could not handle this payment ({e}). Try again later."
+            ),
+            CarbineError::NoGateway => {
+                "This federation has no Lightning gateway right now, try again later.".to_string()
+            }
+            CarbineError::AlreadyPaid => {
+                "This invoice was already paid, or a payment for it is still in progress."
+                    .to_string()
+            }
+            CarbineError::AmountTooSmall => {
+                "This amount is too small to receive over Lightning, enter a larger one."
+                    .to_string()
+            }
+            CarbineError::InvalidInvoice(e) => {
+                format!("This is not a valid Lightning invoice ({e}).")
+            }
+            CarbineError::AmountlessInvoice(reason) => reason.clone(),
+            CarbineError::InvalidAddress(e) => {
+                format!("This bitcoin address can not be used ({e}).")
+            }
+            CarbineError::OperationFailed(e) => e.clone(),
+            CarbineError::Client(e) => format!("The federation client failed: {e}"),
+            CarbineError::Database(e) => format!("Could not access the wallet database: {e}"),
+            CarbineError::UnsupportedSchema { .. } => {
+                "This wallet was opened by a newer version of carbine, update carbine to use it."
+                    .to_string()
+            }
+            CarbineError::ServiceUnavailable(e) => {
+                format!("The wallet could not be opened ({e}). Restart carbine and try again.")
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+use fedimint_core::config::FederationId;
+use serde::Serialize;
+
+use crate::{error::CarbineError, multimint::Multimint, operations::OperationRecord};
+
+const SECS_PER_DAY: u64 = 86400;
+
+/// File formats the history can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Csv, ExportFormat::Json];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.label() == label)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Which operations to export.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ExportFilter {
+    /// Only operations started at or after this time.
+    pub from: Option<SystemTime>,
+    /// Only operations started before this time.
+    pub until: Option<SystemTime>,
+    /// Only operations of these federations, all federations if `None`.
+    pub federations: Option<BTreeSet<FederationId>>,
+}
+
+impl ExportFilter {
+    fn includes(&self, record: &OperationRecord) -> bool {
+        self.from.is_none_or(|from| record.started_at >= from)
+            && self.until.is_none_or(|until| record.started_at < until)
+            && self
+                .federations
+                .as_ref()
+                .is_none_or(|federations| federations.contains(&record.federation_id))
+    }
+}
+
+/// One exported operation. Amounts are in millisatoshis so no precision is
+/// lost, whatever unit the UI shows.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ExportRow {
+    pub date: String,
+    pub federation: String,
+    pub federation_id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub amount_msat: Option<u64>,
+    pub fee_msat: Option<u64>,
+    pub status: String,
+    pub counterparty: Option<String>,
+    pub label: String,
+    /// Payment hash of Lightning payments, transaction id of on-chain ones.
+    pub reference: Option<String>,
+}
+
+impl ExportRow {
+    const CSV_HEADER: [&'static str; 10] = [
+        "date",
+        "federation",
+        "federation_id",
+        "type",
+        "amount_msat",
+        "fee_msat",
+        "status",
+        "counterparty",
+        "label",
+        "payment_hash_or_txid",
+    ];
+
+    fn new(record: &OperationRecord, federation: String) -> Self {
+        ExportRow {
+            date: format_timestamp(record.started_at),
+            federation,
+            federation_id: record.federation_id.to_string(),
+            kind: record.kind.label().to_string(),
+            amount_msat: record.amount.map(|amount| amount.msats),
+            fee_msat: record.fee.map(|fee| fee.msats),
+            status: record.state(),
+            counterparty: record.counterparty.clone(),
+            label: record.annotation.label.clone(),
+            reference: record.reference.clone(),
+        }
+    }
+
+    fn csv_fields(&self) -> [String; 10] {
+        let optional =
+            |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+        [
+            self.date.clone(),
+            self.federation.clone(),
+            self.federation_id.clone(),
+            self.kind.clone(),
+            optional(self.amount_msat),
+            optional(self.fee_msat),
+            self.status.clone(),
+            self.counterparty.clone().unwrap_or_default(),
+            self.label.clone(),
+            self.reference.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Multimint {
+    /// Exports the operations matching `filter`, oldest first. Does not depend
+    /// on the UI, so any frontend can offer it.
+    pub(crate) async fn export_history(
+        &self,
+        filter: &ExportFilter,
+        format: ExportFormat,
+    ) -> Result<String, CarbineError> {
+        let configs = self.federation_configs().await;
+        let mut rows = Vec::new();
+        for (federation_id, config) in configs {
+            if filter
+                .federations
+                .as_ref()
+                .is_some_and(|federations| !federations.contains(&federation_id))
+            {
+                continue;
+            }
+            // Federations that are offline are skipped unless asked for
+            let records = match self.all_operations(&federation_id).await {
+                Ok(records) => records,
+                Err(CarbineError::FederationUnavailable(_)) if filter.federations.is_none() => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            for record in records {
+                if filter.includes(&record) {
+                    rows.push((
+                        record.started_at,
+                        ExportRow::new(&record, config.federation_name.clone()),
+                    ));
+                }
+            }
+        }
+        rows.sort_by_key(|(started_at, _)| *started_at);
+        let rows = rows.into_iter().map(|(_, row)| row).collect::<Vec<_>>();
+
+        match format {
+            ExportFormat::Csv => Ok(to_csv(&rows)),
+            ExportFormat::Json => serde_json::to_string_pretty(&rows)
+                .map_err(|e| CarbineError::OperationFailed(e.to_string())),
+        }
+    }
+}
+
+fn to_csv(rows: &[ExportRow]) -> String {
+    let mut csv = ExportRow::CSV_HEADER.join(",");
+    csv.push_str("\r\n");
+    for row in rows {
+        let fields = row.csv_fields().map(|field| escape_csv(&field));
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Quotes a field if it contains a separator, quote or line break. Fields a
+/// spreadsheet would evaluate as a formula, like a label `=HYPERLINK(...)`,
+/// are prefixed with `'` so they are shown as text.
+fn escape_csv(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{field}")
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Formats `time` as an ISO 8601 UTC timestamp, e.g. `2025-04-01T12:30:00Z`.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    let secs_of_day = secs % SECS_PER_DAY;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses a `YYYY-MM-DD` date, as produced by date inputs, as midnight UTC.
+pub(crate) fn parse_date(date: &str) -> anyhow::Result<SystemTime> {
+    let mut parts = date.trim().splitn(3, '-').map(str::parse::<u64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("Dates must look like 2025-01-31");
+    };
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        bail!("{date} is not a valid date");
+    }
+    let days = days_from_civil(year, month, day);
+    // Days past the end of the month, e.g. 2025-02-30, end up in the next one
+    if civil_from_days(days) != (year, month, day) {
+        bail!("{date} is not a valid date");
+    }
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(days * SECS_PER_DAY))
+        .context("Date is too far in the future")
+}
+
+/// Converts days since the Unix epoch to a `(year, month, day)` date, using
+/// Howard Hinnant's algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of `civil_from_days`, for dates from 1970 on.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn converts_days_both_ways() {
+        // Every day from 1970 to past 2100, which is not a leap year
+        for days in 0..50_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(at(951_782_400 + 45_005)),
+            "2000-02-29T12:30:05Z"
+        );
+        assert_eq!(format_timestamp(at(1_735_689_599)), "2024-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1970-01-01").unwrap(), UNIX_EPOCH);
+        assert_eq!(parse_date(" 2000-02-29 ").unwrap(), at(951_782_400));
+        assert_eq!(parse_date("2024-02-29").unwrap(), at(1_709_164_800));
+        assert_eq!(parse_date("2024-12-31").unwrap(), at(1_735_603_200));
+        assert_eq!(parse_date("2025-01-01").unwrap(), at(1_735_689_600));
+
+        for invalid in [
+            "1969-12-31",
+            "2023-02-29",
+            "2100-02-29",
+            "2025-04-31",
+            "2025-13-01",
+            "2025-00-10",
+            "2025-01-00",
+            "2025-01-32",
+            "2025-1",
+            "25-01-01-01",
+            "yesterday",
+            "",
+        ] {
+            assert!(parse_date(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn escapes_csv_fields() {
+        let row = ExportRow {
+            date: "2025-04-01T12:30:00Z".to_string(),
+            federation: "Bits, \"Bobs\" & co".to_string(),
+            federation_id: "fid".to_string(),
+            kind: "Lightning send".to_string(),
+            amount_msat: Some(21_000),
+            fee_msat: None,
+            status: "Complete".to_string(),
+            counterparty: Some("=HYPERLINK(\"https://evil.example\")".to_string()),
+            label: "line\nbreak".to_string(),
+            reference: Some("@SUM(A1)".to_string()),
+        };
+
+        let csv = to_csv(&[row]);
+        let mut lines = csv.split("\r\n");
+        assert_eq!(lines.next(), Some(ExportRow::CSV_HEADER.join(",").as_str()));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "2025-04-01T12:30:00Z,\"Bits, \"\"Bobs\"\" & co\",fid,Lightning send,21000,,\
+                 Complete,\"'=HYPERLINK(\"\"https://evil.example\"\")\",\"line\nbreak\",'@SUM(A1)"
+            )
+        );
+
+        assert_eq!(escape_csv("plain"), "plain");
+        assert_eq!(escape_csv("+1"), "'+1");
+        assert_eq!(escape_csv("-1"), "'-1");
+        assert_eq!(escape_csv("\tcmd"), "'\tcmd");
+        assert_eq!(escape_csv("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(escape_csv("a=b"), "a=b");
+    }
+}
@@ -0,0 +1,330 @@
+use std::time::{Duration, SystemTime};
+
+use argon2::Argon2;
+use fedimint_core::{
+    bitcoin::hashes::{sha256, Hash},
+    secp256k1::rand::{thread_rng, Rng},
+    Amount,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CarbineError;
+
+/// How long after entering the PIN payments above the confirmation threshold
+/// go through without asking again.
+pub(crate) const CONFIRMATION_WINDOW: Duration = Duration::from_secs(2 * 60);
+
+const PIN_LENGTH: std::ops::RangeInclusive<usize> = 4..=12;
+
+/// Wrong PINs in a row that are tolerated before further attempts are delayed.
+const FREE_PIN_ATTEMPTS: u32 = 3;
+
+/// Delay after the first wrong PIN past `FREE_PIN_ATTEMPTS`, doubled with
+/// every further one up to `MAX_PIN_DELAY`.
+const PIN_DELAY: Duration = Duration::from_secs(30);
+
+const MAX_PIN_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Caps on outgoing payments, `None` leaves that cap off.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SpendingLimits {
+    pub per_payment: Option<Amount>,
+    /// Total of the last 24 hours, including the new payment.
+    pub daily: Option<Amount>,
+    /// Total of the last 7 days, including the new payment.
+    pub weekly: Option<Amount>,
+}
+
+impl SpendingLimits {
+    /// The caps that are set.
+    pub fn caps(&self) -> impl Iterator<Item = (LimitKind, Amount)> {
+        [
+            (LimitKind::PerPayment, self.per_payment),
+            (LimitKind::Daily, self.daily),
+            (LimitKind::Weekly, self.weekly),
+        ]
+        .into_iter()
+        .filter_map(|(kind, limit)| limit.map(|limit| (kind, limit)))
+    }
+
+    pub fn get(&self, kind: LimitKind) -> Option<Amount> {
+        match kind {
+            LimitKind::PerPayment => self.per_payment,
+            LimitKind::Daily => self.daily,
+            LimitKind::Weekly => self.weekly,
+        }
+    }
+
+    pub fn set(&mut self, kind: LimitKind, limit: Option<Amount>) {
+        match kind {
+            LimitKind::PerPayment => self.per_payment = limit,
+            LimitKind::Daily => self.daily = limit,
+            LimitKind::Weekly => self.weekly = limit,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.caps().next().is_none()
+    }
+
+    /// The longest window of the caps that are set, `None` if only a single
+    /// payment is capped.
+    pub fn longest_window(&self) -> Option<Duration> {
+        self.caps().filter_map(|(kind, _)| kind.window()).max()
+    }
+
+    /// Checks a payment of `amount` at `now` against the caps, counting the
+    /// earlier payments in `spends` made within each cap's window.
+    pub fn check(
+        &self,
+        scope: LimitScope,
+        amount: Amount,
+        spends: &[(SystemTime, Amount)],
+        now: SystemTime,
+    ) -> Result<(), CarbineError> {
+        for (kind, limit) in self.caps() {
+            let spent = kind.window().map_or(Amount::ZERO, |window| {
+                let since = now - window;
+                spends
+                    .iter()
+                    .filter(|(at, _)| *at >= since)
+                    .fold(Amount::ZERO, |spent, (_, amount)| spent + *amount)
+            });
+            if spent + amount > limit {
+                return Err(CarbineError::LimitExceeded {
+                    kind,
+                    scope,
+                    limit,
+                    remaining: limit.saturating_sub(spent),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LimitKind {
+    PerPayment,
+    Daily,
+    Weekly,
+}
+
+impl LimitKind {
+    pub const ALL: [LimitKind; 3] = [LimitKind::PerPayment, LimitKind::Daily, LimitKind::Weekly];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LimitKind::PerPayment => "Per payment",
+            LimitKind::Daily => "Daily",
+            LimitKind::Weekly => "Weekly",
+        }
+    }
+
+    /// Rolling window the cap applies to, `None` for a single payment.
+    pub fn window(&self) -> Option<Duration> {
+        match self {
+            LimitKind::PerPayment => None,
+            LimitKind::Daily => Some(Duration::from_secs(24 * 60 * 60)),
+            LimitKind::Weekly => Some(Duration::from_secs(7 * 24 * 60 * 60)),
+        }
+    }
+}
+
+/// Whether a limit was set for one federation or for all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LimitScope {
+    Federation,
+    Global,
+}
+
+/// What the PIN was entered for. Only confirming a payment lets payments
+/// above the confirmation threshold through for `CONFIRMATION_WINDOW`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PinPurpose {
+    Unlock,
+    ConfirmPayment,
+    ChangeSettings,
+}
+
+/// How long to wait before the next attempt after `failures` wrong PINs in a
+/// row, so a short PIN can not be guessed by trying them all.
+pub(crate) fn pin_delay(failures: u32) -> Duration {
+    let Some(delayed) = failures.checked_sub(FREE_PIN_ATTEMPTS) else {
+        return Duration::ZERO;
+    };
+    PIN_DELAY
+        .saturating_mul(2u32.saturating_pow(delayed))
+        .min(MAX_PIN_DELAY)
+}
+
+/// Salted Argon2 hash of the user's PIN. It only guards the UI: the wallet
+/// database itself is not encrypted with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PinHash {
+    salt: [u8; 16],
+    /// `None` for PINs set before Argon2 was used, which only have `hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<[u8; 32]>,
+    /// Salted SHA-256 of older PINs, replaced with `key` once the PIN is
+    /// entered, see `is_outdated`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<sha256::Hash>,
+}
+
+impl PinHash {
+    pub fn new(pin: &str) -> Self {
+        let salt = thread_rng().gen();
+        PinHash {
+            salt,
+            key: Some(Self::derive_key(&salt, pin)),
+            hash: None,
+        }
+    }
+
+    pub fn verify(&self, pin: &str) -> bool {
+        match (&self.key, &self.hash) {
+            (Some(key), _) => Self::derive_key(&self.salt, pin) == *key,
+            (None, Some(hash)) => Self::digest(&self.salt, pin) == *hash,
+            (None, None) => false,
+        }
+    }
+
+    /// Whether the PIN should be hashed again with `new`, as it was stored
+    /// with the fast hash of earlier versions.
+    pub fn is_outdated(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// PINs are 4 to 12 digits.
+    pub fn is_valid_pin(pin: &str) -> bool {
+        PIN_LENGTH.contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit())
+    }
+
+    fn derive_key(salt: &[u8], pin: &str) -> [u8; 32] {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(pin.as_bytes(), salt, &mut key)
+            .expect("Salt and key lengths are within Argon2's limits");
+        key
+    }
+
+    fn digest(salt: &[u8], pin: &str) -> sha256::Hash {
+        sha256::Hash::hash(&[salt, pin.as_bytes()].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn counts_spends_within_each_window() {
+        let now = SystemTime::UNIX_EPOCH + 30 * 24 * HOUR;
+        let limits = SpendingLimits {
+            per_payment: Some(Amount::from_sats(5_000)),
+            daily: Some(Amount::from_sats(10_000)),
+            weekly: Some(Amount::from_sats(20_000)),
+        };
+        let spends = [
+            (now - HOUR, Amount::from_sats(4_000)),
+            (now - 23 * HOUR, Amount::from_sats(4_000)),
+            (now - 25 * HOUR, Amount::from_sats(5_000)),
+            (now - 6 * 24 * HOUR, Amount::from_sats(5_000)),
+            (now - 8 * 24 * HOUR, Amount::from_sats(50_000)),
+        ];
+        let check =
+            |amount| limits.check(LimitScope::Global, Amount::from_sats(amount), &spends, now);
+
+        assert_eq!(limits.longest_window(), Some(7 * 24 * HOUR));
+        assert!(check(2_000).is_ok());
+        assert!(matches!(
+            check(5_001),
+            Err(CarbineError::LimitExceeded {
+                kind: LimitKind::PerPayment,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check(2_001),
+            Err(CarbineError::LimitExceeded {
+                kind: LimitKind::Daily,
+                remaining,
+                ..
+            }) if remaining == Amount::from_sats(2_000)
+        ));
+
+        let weekly_only = SpendingLimits {
+            weekly: Some(Amount::from_sats(20_000)),
+            ..Default::default()
+        };
+        assert!(weekly_only
+            .check(LimitScope::Global, Amount::from_sats(2_000), &spends, now)
+            .is_ok());
+        assert!(matches!(
+            weekly_only.check(
+                LimitScope::Federation,
+                Amount::from_sats(2_001),
+                &spends,
+                now
+            ),
+            Err(CarbineError::LimitExceeded {
+                kind: LimitKind::Weekly,
+                scope: LimitScope::Federation,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn without_caps_allows_anything() {
+        let limits = SpendingLimits::default();
+        let spends = [(SystemTime::UNIX_EPOCH, Amount::from_sats(u64::MAX / 4_000))];
+        assert_eq!(limits.longest_window(), None);
+        assert!(limits
+            .check(
+                LimitScope::Global,
+                Amount::from_sats(1_000_000),
+                &spends,
+                SystemTime::UNIX_EPOCH
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn verifies_pins() {
+        let hash = PinHash::new("1234");
+        assert!(hash.verify("1234"));
+        assert!(!hash.verify("12345"));
+        assert!(!hash.verify("4321"));
+        assert!(!hash.is_outdated());
+        assert_ne!(PinHash::new("1234"), hash, "PINs are salted");
+    }
+
+    #[test]
+    fn verifies_outdated_pins() {
+        let salt = [7; 16];
+        let hash = PinHash {
+            salt,
+            key: None,
+            hash: Some(PinHash::digest(&salt, "1234")),
+        };
+        assert!(hash.verify("1234"));
+        assert!(!hash.verify("4321"));
+        assert!(hash.is_outdated());
+    }
+
+    #[test]
+    fn delays_repeated_wrong_pins() {
+        assert_eq!(pin_delay(0), Duration::ZERO);
+        assert_eq!(pin_delay(2), Duration::ZERO);
+        assert_eq!(pin_delay(3), Duration::from_secs(30));
+        assert_eq!(pin_delay(4), Duration::from_secs(60));
+        assert_eq!(pin_delay(8), Duration::from_secs(960));
+        assert_eq!(pin_delay(10), HOUR);
+        assert_eq!(pin_delay(u32::MAX), HOUR);
+    }
+}
@@ -0,0 +1,477 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+use fedimint_core::{
+    bitcoin::hashes::{sha256, Hash},
+    Amount,
+};
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// Turns a Lightning Address (`user@domain`) or a bech32 encoded `lnurl1...`
+/// string into the URL of the LNURL endpoint it refers to.
+pub(crate) fn decode_lnurl(input: &str) -> anyhow::Result<Url> {
+    let input = input.trim();
+    let input = strip_prefix_ignore_case(input, "lightning:").unwrap_or(input);
+
+    if let Some((user, domain)) = input.split_once('@') {
+        ensure!(
+            !user.is_empty() && !domain.is_empty(),
+            "Invalid Lightning Address"
+        );
+        let url = format!(
+            "{}://{domain}/.well-known/lnurlp/{user}",
+            scheme_for_domain(domain)
+        );
+        return Ok(Url::parse(&url)?);
+    }
+
+    if let Some(rest) = strip_prefix_ignore_case(input, "lnurlp://")
+        .or_else(|| strip_prefix_ignore_case(input, "lnurlw://"))
+    {
+        let domain = rest.split('/').next().unwrap_or_default();
+        return Ok(Url::parse(&format!(
+            "{}://{rest}",
+            scheme_for_domain(domain)
+        ))?);
+    }
+
+    let (hrp, data) = bech32::decode(input).context("Invalid LNURL encoding")?;
+    ensure!(hrp.to_lowercase() == "lnurl", "Not an LNURL");
+    let url = String::from_utf8(data).context("LNURL does not contain a valid URL")?;
+    Ok(Url::parse(&url)?)
+}
+
+/// Returns true if the input looks like something `decode_lnurl` can handle.
+pub(crate) fn is_lnurl(input: &str) -> bool {
+    decode_lnurl(input).is_ok()
+}
+
+/// `input` without `prefix`, which may be in any case, e.g. a URI scheme.
+pub(crate) fn strip_prefix_ignore_case<'a>(input: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = input.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &input[prefix.len()..])
+}
+
+/// LNURL services on onion addresses or a local machine are reachable over
+/// plain HTTP, everything else must use TLS.
+fn scheme_for_domain(domain: &str) -> &'static str {
+    let host = domain.split(':').next().unwrap_or(domain);
+    if host.ends_with(".onion") || host == "localhost" || host == "127.0.0.1" {
+        "http"
+    } else {
+        "https"
+    }
+}
+
+/// LUD-06 `payRequest` as returned by the first call to an LNURL-pay endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PayRequest {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub metadata: String,
+    #[serde(default)]
+    pub comment_allowed: u64,
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayResponse {
+    pr: String,
+}
+
+impl PayRequest {
+    pub fn min_sendable(&self) -> Amount {
+        Amount::from_msats(self.min_sendable)
+    }
+
+    pub fn max_sendable(&self) -> Amount {
+        Amount::from_msats(self.max_sendable)
+    }
+
+    /// The human readable `text/plain` entry of the metadata.
+    pub fn description(&self) -> Option<String> {
+        self.metadata_entry("text/plain")
+    }
+
+    /// The `text/identifier` or `text/email` entry of the metadata, which is
+    /// set for Lightning Addresses.
+    pub fn identifier(&self) -> Option<String> {
+        self.metadata_entry("text/identifier")
+            .or_else(|| self.metadata_entry("text/email"))
+    }
+
+    fn metadata_entry(&self, kind: &str) -> Option<String> {
+        let entries: Vec<(String, serde_json::Value)> =
+            serde_json::from_str(&self.metadata).ok()?;
+        entries
+            .into_iter()
+            .find(|(entry_kind, _)| entry_kind == kind)
+            .and_then(|(_, value)| value.as_str().map(ToOwned::to_owned))
+    }
+}
+
+/// LUD-03 `withdrawRequest` as returned by the first call to an
+/// LNURL-withdraw endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WithdrawRequest {
+    pub callback: String,
+    pub k1: String,
+    #[serde(default)]
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+    pub tag: String,
+}
+
+impl WithdrawRequest {
+    pub fn min_withdrawable(&self) -> Amount {
+        Amount::from_msats(self.min_withdrawable)
+    }
+
+    pub fn max_withdrawable(&self) -> Amount {
+        Amount::from_msats(self.max_withdrawable)
+    }
+}
+
+/// Fetches the `payRequest` for an LNURL-pay code or Lightning Address.
+pub(crate) async fn resolve_pay(input: &str) -> anyhow::Result<PayRequest> {
+    let url = decode_lnurl(input)?;
+    let pay_request: PayRequest = get_json(url).await?;
+    ensure!(
+        pay_request.tag == "payRequest",
+        "LNURL is not a pay request"
+    );
+    ensure!(
+        pay_request.min_sendable <= pay_request.max_sendable,
+        "LNURL service returned an invalid amount range"
+    );
+    Ok(pay_request)
+}
+
+/// Requests an invoice for `amount` from the LNURL-pay callback and verifies
+/// that it commits to the amount and metadata we were shown.
+pub(crate) async fn fetch_invoice(
+    pay_request: &PayRequest,
+    amount: Amount,
+    comment: Option<String>,
+) -> anyhow::Result<Bolt11Invoice> {
+    ensure!(
+        pay_request.min_sendable <= amount.msats && amount.msats <= pay_request.max_sendable,
+        "Amount must be between {} and {}",
+        pay_request.min_sendable(),
+        pay_request.max_sendable()
+    );
+
+    let mut callback = Url::parse(&pay_request.callback)?;
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount.msats.to_string());
+    if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+        ensure!(
+            comment.chars().count() as u64 <= pay_request.comment_allowed,
+            "Comment is longer than the {} characters allowed",
+            pay_request.comment_allowed
+        );
+        callback.query_pairs_mut().append_pair("comment", &comment);
+    }
+
+    let response: PayResponse = get_json(callback).await?;
+    let invoice = Bolt11Invoice::from_str(&response.pr)?;
+
+    ensure!(
+        invoice.amount_milli_satoshis() == Some(amount.msats),
+        "Invoice amount does not match the requested amount"
+    );
+    let expected_hash = sha256::Hash::hash(pay_request.metadata.as_bytes());
+    match invoice.description() {
+        Bolt11InvoiceDescription::Hash(hash) if hash.0 == expected_hash => {}
+        _ => bail!("Invoice description hash does not match the LNURL metadata"),
+    }
+
+    Ok(invoice)
+}
+
+/// Returns the `tag` of the LNURL endpoint, which tells pay and withdraw
+/// requests apart.
+pub(crate) async fn fetch_tag(input: &str) -> anyhow::Result<String> {
+    let url = decode_lnurl(input)?;
+    let value: serde_json::Value = get_json(url).await?;
+    value
+        .get("tag")
+        .and_then(|tag| tag.as_str())
+        .map(ToOwned::to_owned)
+        .context("LNURL response has no tag")
+}
+
+/// Fetches the `withdrawRequest` for an LNURL-withdraw code.
+pub(crate) async fn resolve_withdraw(input: &str) -> anyhow::Result<WithdrawRequest> {
+    let url = decode_lnurl(input)?;
+    let withdraw_request: WithdrawRequest = get_json(url).await?;
+    ensure!(
+        withdraw_request.tag == "withdrawRequest",
+        "LNURL is not a withdraw request"
+    );
+    ensure!(
+        withdraw_request.min_withdrawable <= withdraw_request.max_withdrawable,
+        "LNURL service returned an invalid amount range"
+    );
+    Ok(withdraw_request)
+}
+
+/// Hands our invoice to the LNURL-withdraw callback. The service pays the
+/// invoice asynchronously, so success only means the request was accepted.
+pub(crate) async fn submit_withdraw(
+    withdraw_request: &WithdrawRequest,
+    invoice: &str,
+) -> anyhow::Result<()> {
+    let mut callback = Url::parse(&withdraw_request.callback)?;
+    callback
+        .query_pairs_mut()
+        .append_pair("k1", &withdraw_request.k1)
+        .append_pair("pr", invoice);
+
+    let _: serde_json::Value = get_json(callback).await?;
+    Ok(())
+}
+
+async fn get_json<T: DeserializeOwned>(url: Url) -> anyhow::Result<T> {
+    let value: serde_json::Value = reqwest::get(url).await?.error_for_status()?.json().await?;
+    if value.get("status").and_then(|status| status.as_str()) == Some("ERROR") {
+        let reason = value
+            .get("reason")
+            .and_then(|reason| reason.as_str())
+            .unwrap_or("unknown error");
+        bail!("LNURL service returned an error: {reason}");
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fedimint_core::secp256k1::{Secp256k1, SecretKey};
+    use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const METADATA: &str = r#"[["text/plain","Coffee"],["text/identifier","alice@127.0.0.1"]]"#;
+
+    /// Stands in for an LNURL service on a local port, answering every
+    /// request with the JSON `respond` returns for its path and query and
+    /// the port.
+    async fn serve(
+        respond: impl Fn(&str, u16) -> serde_json::Value + Send + Sync + 'static,
+    ) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![0; 8192];
+                    let read = stream.read(&mut request).await.unwrap_or_default();
+                    let request = String::from_utf8_lossy(&request[..read]);
+                    let target = request.split_whitespace().nth(1).unwrap_or("/");
+                    let body = respond(target, port).to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn pay_request(port: u16) -> PayRequest {
+        PayRequest {
+            callback: format!("http://127.0.0.1:{port}/callback"),
+            min_sendable: 1_000,
+            max_sendable: 1_000_000,
+            metadata: METADATA.to_string(),
+            comment_allowed: 5,
+            tag: "payRequest".to_string(),
+        }
+    }
+
+    fn invoice(msats: u64, metadata: &str) -> Bolt11Invoice {
+        let key = SecretKey::from_slice(&[42; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Regtest)
+            .description_hash(sha256::Hash::hash(metadata.as_bytes()))
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([2; 32]))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(msats)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+            .unwrap()
+    }
+
+    /// The `amount` query parameter of a callback request.
+    fn requested_msats(target: &str) -> u64 {
+        Url::parse(&format!("http://localhost{target}"))
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "amount")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap()
+    }
+
+    #[test]
+    fn decodes_lightning_addresses() {
+        assert_eq!(
+            decode_lnurl("alice@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert_eq!(
+            decode_lnurl(" lightning:bob@localhost:8080 ").unwrap().as_str(),
+            "http://localhost:8080/.well-known/lnurlp/bob"
+        );
+        assert!(decode_lnurl("@example.com").is_err());
+        assert!(decode_lnurl("alice@").is_err());
+    }
+
+    #[test]
+    fn decodes_bech32_lnurls() {
+        let url = "https://service.com/api/v1/lnurl?q=3fc3645b";
+        let hrp = bech32::Hrp::parse("lnurl").unwrap();
+        let lnurl = bech32::encode::<bech32::Bech32>(hrp, url.as_bytes()).unwrap();
+
+        assert_eq!(decode_lnurl(&lnurl).unwrap().as_str(), url);
+        assert_eq!(decode_lnurl(&lnurl.to_uppercase()).unwrap().as_str(), url);
+        assert_eq!(
+            decode_lnurl(&format!("LIGHTNING:{lnurl}")).unwrap().as_str(),
+            url
+        );
+
+        let hrp = bech32::Hrp::parse("lnbc").unwrap();
+        let other = bech32::encode::<bech32::Bech32>(hrp, url.as_bytes()).unwrap();
+        assert!(decode_lnurl(&other).is_err());
+        assert!(decode_lnurl("not an lnurl").is_err());
+    }
+
+    #[test]
+    fn decodes_lud17_schemes() {
+        assert_eq!(
+            decode_lnurl("lnurlp://service.com/pay/1").unwrap().as_str(),
+            "https://service.com/pay/1"
+        );
+        assert_eq!(
+            decode_lnurl("lnurlw://abc.onion/withdraw").unwrap().as_str(),
+            "http://abc.onion/withdraw"
+        );
+    }
+
+    #[tokio::test]
+    async fn pays_lightning_address_of_local_service() {
+        let port = serve(|target, port| {
+            if target.starts_with("/.well-known/lnurlp/alice") {
+                json!({
+                    "callback": format!("http://127.0.0.1:{port}/callback"),
+                    "minSendable": 1_000,
+                    "maxSendable": 1_000_000,
+                    "metadata": METADATA,
+                    "commentAllowed": 5,
+                    "tag": "payRequest",
+                })
+            } else {
+                json!({ "pr": invoice(requested_msats(target), METADATA).to_string() })
+            }
+        })
+        .await;
+
+        let request = resolve_pay(&format!("alice@127.0.0.1:{port}")).await.unwrap();
+        assert_eq!(request.description().as_deref(), Some("Coffee"));
+        assert_eq!(request.identifier().as_deref(), Some("alice@127.0.0.1"));
+        assert_eq!(request.min_sendable(), Amount::from_msats(1_000));
+        assert_eq!(request.max_sendable(), Amount::from_msats(1_000_000));
+
+        let invoice = fetch_invoice(&request, Amount::from_msats(21_000), None)
+            .await
+            .unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(21_000));
+    }
+
+    #[tokio::test]
+    async fn rejects_invoice_for_other_amount() {
+        let port = serve(|target, _| {
+            json!({ "pr": invoice(requested_msats(target) + 1_000, METADATA).to_string() })
+        })
+        .await;
+
+        let error = fetch_invoice(&pay_request(port), Amount::from_msats(21_000), None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("amount"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn rejects_invoice_for_other_metadata() {
+        let port = serve(|target, _| {
+            json!({ "pr": invoice(requested_msats(target), "[]").to_string() })
+        })
+        .await;
+
+        let error = fetch_invoice(&pay_request(port), Amount::from_msats(21_000), None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("description hash"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn rejects_amounts_outside_the_range() {
+        // Nothing listens on the callback, the amount is checked before the
+        // callback is called
+        let request = pay_request(1);
+        assert!(fetch_invoice(&request, Amount::from_msats(999), None)
+            .await
+            .is_err());
+        assert!(fetch_invoice(&request, Amount::from_msats(1_000_001), None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn counts_comment_length_in_characters() {
+        let port = serve(|target, _| {
+            json!({ "pr": invoice(requested_msats(target), METADATA).to_string() })
+        })
+        .await;
+        let request = pay_request(port);
+        let amount = Amount::from_msats(21_000);
+
+        // Five characters but ten bytes
+        fetch_invoice(&request, amount, Some("ééééé".to_string()))
+            .await
+            .unwrap();
+        let error = fetch_invoice(&request, amount, Some("éééééé".to_string()))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("characters"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn reports_service_errors() {
+        let port = serve(|_, _| json!({ "status": "ERROR", "reason": "Unknown user" })).await;
+
+        let error = resolve_pay(&format!("nobody@127.0.0.1:{port}"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Unknown user"), "{error}");
+    }
+}
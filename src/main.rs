@@ -1,116 +1,137 @@
 mod components;
 mod db;
+mod error;
+mod export;
+mod limits;
+mod lnurl;
+mod meta;
+mod migrations;
 mod multimint;
-
-use std::{fmt::Display, sync::Arc};
-
-use components::{dashboard::Dashboard, join::JoinFederationForm};
+mod nostr;
+mod notifications;
+mod operations;
+mod parser;
+mod price;
+mod scan;
+mod service;
+mod settings;
+mod units;
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use components::{
+    contacts::{Contacts, EditContact, NewContact},
+    dashboard::Federation,
+    discover::Discover,
+    export::Export,
+    history::{FederationHistory, Operation},
+    home::{Home, NotFound},
+    join::Join,
+    layout::Shell,
+    settings::Settings,
+};
 use dioxus::prelude::*;
+use fedimint_client::OperationId;
 use fedimint_core::config::FederationId;
-use multimint::Multimint;
-use tokio::sync::RwLock;
+use meta::FederationMeta;
+use multimint::FederationStatus;
+use operations::PendingOperation;
+use price::{FiatRate, PriceSource};
+use service::WalletHandle;
+use settings::Settings;
+use units::DisplayUnit;
 
 const MAIN_CSS: Asset = asset!("/assets/main.css");
 
-static MULTIMINT: GlobalSignal<Arc<RwLock<Option<Multimint>>>> =
-    Global::new(|| Arc::new(RwLock::new(None)));
+static WALLET: GlobalSignal<WalletHandle> = Global::new(WalletHandle::spawn);
 
-fn main() {
-    dioxus::launch(app);
-}
+/// Joined federations, `None` until they have been read from the database.
+static FEDERATIONS: GlobalSignal<Option<Vec<FederationSelector>>> = Global::new(|| None);
 
-async fn load_multimint() -> Arc<RwLock<Option<Multimint>>> {
-    if MULTIMINT().read().await.is_none() {
-        *MULTIMINT.write() = Arc::new(RwLock::new(Some(
-            Multimint::new().await.expect("Could not create multimint"),
-        )));
-    }
+/// Latest status of every joined federation, mirrored from the wallet service.
+static FEDERATION_STATUSES: GlobalSignal<BTreeMap<FederationId, FederationStatus>> =
+    Global::new(BTreeMap::new);
+static PENDING_OPERATIONS: GlobalSignal<Vec<PendingOperation>> = Global::new(Vec::new);
+
+/// The user's settings, loaded from the database on startup. Change them with
+/// `settings::update_settings` so they are persisted.
+static SETTINGS: GlobalSignal<Settings> = Global::new(Settings::default);
+/// Why the stored settings could not be read. They are not saved over while
+/// it is set, see `update_settings`.
+static SETTINGS_ERROR: GlobalSignal<Option<String>> = Global::new(|| None);
+static DISPLAY_UNIT: GlobalMemo<DisplayUnit> = Memo::global(|| SETTINGS.read().display_unit);
+static FIAT_CURRENCY: GlobalMemo<Option<String>> =
+    Memo::global(|| SETTINGS.read().fiat_currency.clone());
+static PRICE_SOURCE: GlobalMemo<PriceSource> =
+    Memo::global(|| SETTINGS.read().price_source.clone());
+static FIAT_RATE: GlobalSignal<Option<FiatRate>> = Global::new(|| None);
+
+/// Why the PIN dialog is shown, `None` while it is hidden.
+static PIN_PROMPT: GlobalSignal<Option<PinPrompt>> = Global::new(|| None);
 
-    MULTIMINT()
+const PRICE_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const NOTICE_DURATION: Duration = Duration::from_secs(6);
+
+fn main() {
+    dioxus::launch(app);
 }
 
 #[component]
 pub fn app() -> Element {
-    let sidebar_items = use_signal(|| Vec::new());
-    let mut selected_federation = use_signal(|| None::<FederationSelector>);
-
-    let load_items = {
-        to_owned![sidebar_items];
-        move || {
-            spawn({
-                to_owned![sidebar_items];
-                async move {
-                    let multimint = load_multimint().await;
-                    let mm = multimint.read().await;
-                    if let Some(mm) = mm.as_ref() {
-                        let names = mm.federations().await;
-                        sidebar_items.set(names);
-                    }
-                }
-            });
-        }
-    };
-
-    use_effect(move || {
-        load_items();
-    });
-
     rsx! {
         link { rel: "stylesheet", href: "{MAIN_CSS}" }
-        div {
-            class: "container",
-            // Sidebar
-            div {
-                class: "sidebar",
-                h2 { class: "sidebar-title", "Federations" }
-                ul {
-                    class: "sidebar-list",
-                    for item in sidebar_items().iter().cloned() {
-                        li {
-                            class: "sidebar-item",
-                            onclick: move |_| {
-                                selected_federation.set(Some(item.clone()));
-                            },
-                            "{item}"
-                        }
-                    }
-                }
-            }
-
-            // Main content
-            div {
-                class: "main",
-
-                button {
-                    class: "add-button",
-                    onclick: move |_| {
-                        selected_federation.set(None);
-                    },
-                    "+"
-                }
-
-                match selected_federation() {
-                    Some(selector) => rsx! {
-                        Dashboard { federation_info: selector }
-                    },
-                    None => rsx! {
-                        JoinFederationForm {
-                            on_join_success: move |selector| {
-                                load_items();
-                                selected_federation.set(Some(selector));
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        Router::<Route> {}
     }
 }
 
+/// Screens of the app. Every screen has its own address, so the history and
+/// bookmarks work and features can link to each other.
+#[derive(Clone, Debug, PartialEq, Routable)]
+#[rustfmt::skip]
+enum Route {
+    #[layout(Shell)]
+        #[route("/")]
+        Home {},
+        #[route("/federation/:id")]
+        Federation { id: FederationId },
+        #[route("/federation/:id/history")]
+        FederationHistory { id: FederationId },
+        #[route("/operation/:id")]
+        Operation { id: OperationId },
+        #[route("/contacts")]
+        Contacts {},
+        #[route("/contacts/new")]
+        NewContact {},
+        #[route("/contacts/:id")]
+        EditContact { id: u64 },
+        #[route("/discover")]
+        Discover {},
+        #[route("/export")]
+        Export {},
+        #[route("/settings")]
+        Settings {},
+        #[route("/join?:invite_code")]
+        Join { invite_code: String },
+    #[end_layout]
+    #[route("/:..segments")]
+    NotFound { segments: Vec<String> },
+}
+
+/// Name of a joined federation, empty while the federations are loading.
+fn federation_name(federation_id: &FederationId) -> String {
+    FEDERATIONS()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|item| item.federation_id == *federation_id)
+        .map(|item| item.federation_name)
+        .unwrap_or_default()
+}
+
 #[derive(Clone, Eq, PartialEq)]
 struct FederationSelector {
     federation_name: String,
     federation_id: FederationId,
+    meta: FederationMeta,
 }
 
 impl Display for FederationSelector {
@@ -118,3 +139,25 @@ impl Display for FederationSelector {
         write!(f, "{}", self.federation_name)
     }
 }
+
+/// A flow on the `Dashboard` that a pasted payment string was routed to,
+/// carrying the values to prefill.
+#[derive(Clone, Debug, PartialEq)]
+enum DashboardAction {
+    Send(String),
+    Receive(String),
+    Redeem(String),
+    Withdraw {
+        address: String,
+        amount_sats: Option<u64>,
+    },
+}
+
+/// What the user is asked to enter their PIN for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PinPrompt {
+    /// A payment above the confirmation threshold, retried afterwards.
+    Confirm,
+    /// The wallet locked itself after being idle.
+    Unlock,
+}
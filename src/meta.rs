@@ -0,0 +1,116 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fedimint_core::{
+    encoding::{Decodable, Encodable},
+    Amount,
+};
+use serde::{Deserialize, Serialize};
+
+/// How long before a federation's announced shutdown we start warning.
+pub(crate) const EXPIRY_WARNING: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often the meta of a loaded federation is read again, the client
+/// fetches updates from the federation in the background.
+pub(crate) const META_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const ICON_URL: &str = "federation_icon_url";
+const WELCOME_MESSAGE: &str = "welcome_message";
+const VETTED_GATEWAYS: &str = "vetted_gateways";
+const EXPIRY_TIMESTAMP: &str = "federation_expiry_timestamp";
+const SUCCESSOR: &str = "federation_successor";
+const MAX_BALANCE: &str = "max_balance_msats";
+const MAX_INVOICE: &str = "max_invoice_msats";
+const DEPOSITS_DISABLED: &str = "onchain_deposits_disabled";
+
+/// Meta fields carbine understands. Federations set up for Fedi publish them
+/// with a `fedi:` prefix, which is accepted as well.
+pub(crate) const META_FIELDS: [&str; 8] = [
+    ICON_URL,
+    WELCOME_MESSAGE,
+    VETTED_GATEWAYS,
+    EXPIRY_TIMESTAMP,
+    SUCCESSOR,
+    MAX_BALANCE,
+    MAX_INVOICE,
+    DEPOSITS_DISABLED,
+];
+
+/// What a federation's guardians publish about it besides its name. Fields
+/// that are missing or malformed are left empty.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub(crate) struct FederationMeta {
+    pub icon_url: Option<String>,
+    pub welcome_message: Option<String>,
+    /// Ids of the Lightning gateways the guardians vouch for.
+    pub vetted_gateways: Vec<String>,
+    /// When the federation shuts down, in seconds since the Unix epoch.
+    pub expiry_timestamp: Option<u64>,
+    /// Invite code of the federation users should move their funds to.
+    pub successor: Option<String>,
+    pub max_balance_msats: Option<u64>,
+    pub max_invoice_msats: Option<u64>,
+    pub onchain_deposits_disabled: bool,
+}
+
+impl FederationMeta {
+    /// Parses the raw meta fields, as found in the client config where every
+    /// value is a string.
+    pub fn from_fields(fields: &BTreeMap<String, String>) -> Self {
+        let raw = |key: &str| fields.get(key).or_else(|| fields.get(&format!("fedi:{key}")));
+        let field = |key: &str| {
+            raw(key)
+                .map(|value| value.trim().trim_matches('"').to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let number = |key: &str| field(key).and_then(|value| value.parse::<u64>().ok());
+
+        FederationMeta {
+            icon_url: field(ICON_URL).filter(|url| url.starts_with("https://")),
+            welcome_message: field(WELCOME_MESSAGE),
+            vetted_gateways: raw(VETTED_GATEWAYS)
+                .and_then(|value| serde_json::from_str(value).ok())
+                .unwrap_or_default(),
+            expiry_timestamp: number(EXPIRY_TIMESTAMP),
+            successor: field(SUCCESSOR),
+            max_balance_msats: number(MAX_BALANCE),
+            max_invoice_msats: number(MAX_INVOICE),
+            onchain_deposits_disabled: field(DEPOSITS_DISABLED)
+                .is_some_and(|value| value == "true"),
+        }
+    }
+
+    pub fn expiry(&self) -> Option<SystemTime> {
+        self.expiry_timestamp
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn max_balance(&self) -> Option<Amount> {
+        self.max_balance_msats.map(Amount::from_msats)
+    }
+
+    pub fn max_invoice(&self) -> Option<Amount> {
+        self.max_invoice_msats.map(Amount::from_msats)
+    }
+
+    /// Warning about the federation's announced shutdown, once it is less
+    /// than `EXPIRY_WARNING` away or has passed.
+    pub fn shutdown_warning(&self) -> Option<String> {
+        let expiry = self.expiry()?;
+        let warning = match expiry.duration_since(fedimint_core::time::now()) {
+            Ok(left) if left > EXPIRY_WARNING => return None,
+            Ok(left) => {
+                let days = left.as_secs() / (24 * 60 * 60);
+                match days {
+                    0 => "This federation shuts down within a day.".to_string(),
+                    1 => "This federation shuts down within two days.".to_string(),
+                    days => format!("This federation shuts down in {days} days."),
+                }
+            }
+            Err(_) => "This federation has shut down.".to_string(),
+        };
+        Some(format!("{warning} Move your funds to another federation before it is gone."))
+    }
+}
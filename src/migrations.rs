@@ -0,0 +1,266 @@
+use dioxus::logger::tracing::info;
+use fedimint_core::db::{Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
+use futures_util::StreamExt;
+
+use crate::{
+    db::{
+        FederationConfig, FederationConfigKey, FederationConfigKeyPrefixV0, SchemaVersion,
+        SchemaVersionKey,
+    },
+    error::CarbineError,
+    meta::FederationMeta,
+};
+
+/// Version of the layout of our `DbKeyPrefix` records. Bump it and add a
+/// step to `migrate_from` whenever a stored record changes.
+pub(crate) const SCHEMA_VERSION: u64 = 2;
+
+/// Brings the records of an existing wallet up to `SCHEMA_VERSION`. All
+/// steps run in one transaction, so a failed migration leaves the database
+/// untouched.
+pub(crate) async fn run_migrations(db: &Database) -> Result<(), CarbineError> {
+    let mut dbtx = db.begin_transaction().await;
+    let mut version = dbtx
+        .get_value(&SchemaVersionKey)
+        .await
+        .map_or(0, |version| version.0);
+    if version > SCHEMA_VERSION {
+        return Err(CarbineError::UnsupportedSchema {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    while version < SCHEMA_VERSION {
+        info!("Migrating database from schema version {version}");
+        migrate_from(&mut dbtx.to_ref_nc(), version).await?;
+        version += 1;
+    }
+    dbtx.insert_entry(&SchemaVersionKey, &SchemaVersion(version))
+        .await;
+    dbtx.commit_tx_result()
+        .await
+        .map_err(|e| CarbineError::Database(e.to_string()))
+}
+
+/// Migrates the records from schema `version` to the next one.
+async fn migrate_from(
+    dbtx: &mut DatabaseTransaction<'_>,
+    version: u64,
+) -> Result<(), CarbineError> {
+    match version {
+        // Wallets created before the schema was versioned already use the
+        // layout of version 1
+        0 => Ok(()),
+        // Version 2 caches the federation meta in `FederationConfig`. It
+        // starts out empty and is filled in when the client loads.
+        1 => {
+            let configs = dbtx
+                .find_by_prefix(&FederationConfigKeyPrefixV0)
+                .await
+                .collect::<Vec<_>>()
+                .await;
+            for (key, config) in configs {
+                let config = FederationConfig {
+                    invite_code: config.invite_code,
+                    connector: config.connector,
+                    federation_name: config.federation_name,
+                    meta: FederationMeta::default(),
+                };
+                dbtx.insert_entry(&FederationConfigKey { id: key.id }, &config)
+                    .await;
+            }
+            Ok(())
+        }
+        // Only reached if `SCHEMA_VERSION` was bumped without adding a step
+        _ => Err(CarbineError::UnsupportedSchema {
+            found: version,
+            supported: SCHEMA_VERSION,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api_client::api::net::Connector;
+    use fedimint_core::{
+        bitcoin::hashes::{sha256, Hash},
+        config::FederationId,
+        db::mem_impl::MemDatabase,
+        invite_code::InviteCode,
+        util::SafeUrl,
+        Amount, PeerId,
+    };
+
+    use super::*;
+    use crate::{
+        db::{FederationConfigKeyV0, FederationConfigV0, SettingsKey, SettingsRecord},
+        settings::Settings,
+        units::DisplayUnit,
+    };
+
+    fn database() -> Database {
+        Database::new(MemDatabase::new(), Default::default())
+    }
+
+    fn invite_code() -> InviteCode {
+        InviteCode::new(
+            SafeUrl::parse("wss://fedimint.example.com/").unwrap(),
+            PeerId::from(0),
+            FederationId::dummy(),
+            None,
+        )
+    }
+
+    /// Writes a federation config as stored up to schema version 1, and the
+    /// schema version if one is given.
+    async fn write_v0_config(db: &Database, version: Option<u64>) {
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(
+            &FederationConfigKeyV0 {
+                id: FederationId::dummy(),
+            },
+            &FederationConfigV0 {
+                invite_code: invite_code(),
+                connector: Connector::default(),
+                federation_name: "Test federation".to_string(),
+            },
+        )
+        .await;
+        if let Some(version) = version {
+            dbtx.insert_entry(&SchemaVersionKey, &SchemaVersion(version))
+                .await;
+        }
+        dbtx.commit_tx().await;
+    }
+
+    async fn read_schema(db: &Database) -> (Option<FederationConfig>, Option<SchemaVersion>) {
+        let mut dbtx = db.begin_transaction_nc().await;
+        let config = dbtx
+            .get_value(&FederationConfigKey {
+                id: FederationId::dummy(),
+            })
+            .await;
+        (config, dbtx.get_value(&SchemaVersionKey).await)
+    }
+
+    fn migrated_config() -> FederationConfig {
+        FederationConfig {
+            invite_code: invite_code(),
+            connector: Connector::default(),
+            federation_name: "Test federation".to_string(),
+            meta: FederationMeta::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_unversioned_wallets() {
+        let db = database();
+        write_v0_config(&db, None).await;
+
+        run_migrations(&db).await.unwrap();
+
+        assert_eq!(
+            read_schema(&db).await,
+            (Some(migrated_config()), Some(SchemaVersion(SCHEMA_VERSION)))
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_version_1_wallets() {
+        let db = database();
+        write_v0_config(&db, Some(1)).await;
+
+        run_migrations(&db).await.unwrap();
+
+        assert_eq!(
+            read_schema(&db).await,
+            (Some(migrated_config()), Some(SchemaVersion(SCHEMA_VERSION)))
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_new_wallets() {
+        let db = database();
+
+        run_migrations(&db).await.unwrap();
+        run_migrations(&db).await.unwrap();
+
+        assert_eq!(
+            read_schema(&db).await,
+            (None, Some(SchemaVersion(SCHEMA_VERSION)))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_newer_schema() {
+        let db = database();
+        write_v0_config(&db, Some(SCHEMA_VERSION + 1)).await;
+
+        assert!(matches!(
+            run_migrations(&db).await,
+            Err(CarbineError::UnsupportedSchema { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+        let mut dbtx = db.begin_transaction_nc().await;
+        assert_eq!(
+            dbtx.get_value(&SchemaVersionKey).await,
+            Some(SchemaVersion(SCHEMA_VERSION + 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_without_migration_step() {
+        let db = database();
+        let mut dbtx = db.begin_transaction().await;
+
+        assert!(matches!(
+            migrate_from(&mut dbtx.to_ref_nc(), SCHEMA_VERSION).await,
+            Err(CarbineError::UnsupportedSchema { found, .. }) if found == SCHEMA_VERSION
+        ));
+    }
+
+    /// Settings are JSON with defaults for missing fields, so they need no
+    /// migration. Those saved before any later field was added still load.
+    #[tokio::test]
+    async fn keeps_older_settings_readable() {
+        let salt = [7u8; 16];
+        let hash = sha256::Hash::hash(&[salt.as_slice(), b"1234"].concat());
+        let json = serde_json::json!({
+            "display_unit": "Btc",
+            "fiat_currency": "EUR",
+            "auto_lock_minutes": 5,
+            "limits": { "daily": Amount::from_sats(50_000) },
+            "confirm_above": Amount::from_sats(10_000),
+            "pin": { "salt": salt, "hash": hash },
+        });
+        let db = database();
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(
+            &SettingsKey,
+            &SettingsRecord {
+                json: json.to_string(),
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        run_migrations(&db).await.unwrap();
+
+        let mut dbtx = db.begin_transaction_nc().await;
+        let record = dbtx.get_value(&SettingsKey).await.unwrap();
+        let settings = serde_json::from_str::<Settings>(&record.json).unwrap();
+        assert_eq!(settings.display_unit, DisplayUnit::Btc);
+        assert_eq!(settings.fiat_currency.as_deref(), Some("EUR"));
+        assert_eq!(settings.auto_lock_minutes, Some(5));
+        assert_eq!(settings.limits.daily, Some(Amount::from_sats(50_000)));
+        assert_eq!(settings.limits.weekly, None);
+        assert_eq!(settings.confirm_above, Some(Amount::from_sats(10_000)));
+        assert!(settings.pin.is_some_and(|pin| pin.verify("1234")));
+        assert_eq!(settings.price_source, Default::default());
+        assert!(settings.nostr_relays.is_empty());
+    }
+}
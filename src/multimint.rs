@@ -1,56 +1,188 @@
-use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::bail;
 use dioxus::logger::tracing::info;
-use fedimint_api_client::api::net::Connector;
+use fedimint_api_client::api::{net::Connector, FederationError, PeerError};
 use fedimint_bip39::{Bip39RootSecretStrategy, Mnemonic};
 use fedimint_client::{
-    module_init::ClientModuleInitRegistry, secret::RootSecretStrategy, Client, ClientHandleArc,
-    OperationId,
+    module::ClientModuleInstance,
+    module_init::ClientModuleInitRegistry,
+    oplog::{ChronologicalOperationLogKey, OperationLogEntry},
+    secret::RootSecretStrategy,
+    Client, ClientHandleArc, OperationId,
 };
 use fedimint_core::{
+    bitcoin::{address::NetworkUnchecked, Address, Txid},
     config::FederationId,
-    db::{Database, IDatabaseTransactionOpsCoreTyped},
+    db::{Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped},
     encoding::Encodable,
     invite_code::InviteCode,
     secp256k1::rand::thread_rng,
+    util::SafeUrl,
     Amount,
 };
 use fedimint_derive_secret::{ChildId, DerivableSecret};
 use fedimint_ln_client::LightningClientInit;
-use fedimint_lnv2_client::{FinalReceiveOperationState, FinalSendOperationState};
-use fedimint_lnv2_common::Bolt11InvoiceDescription;
-use fedimint_mint_client::MintClientInit;
+use fedimint_lnv2_client::{
+    FinalReceiveOperationState, FinalSendOperationState, LightningOperationMeta, ReceiveError,
+    ReceiveOperationState, SelectGatewayError, SendOperationState, SendPaymentError,
+};
+use fedimint_lnv2_common::{Bolt11InvoiceDescription, LightningInvoice};
+use fedimint_mint_client::{
+    MintClientInit, MintClientModule, MintOperationMeta, MintOperationMetaVariant, OOBNotes,
+    ReissueExternalNotesState, SelectNotesWithAtleastAmount, SpendOOBState,
+};
 use fedimint_rocksdb::RocksDb;
-use fedimint_wallet_client::WalletClientInit;
+use fedimint_wallet_client::{
+    DepositStateV2, PegOutFees, WalletClientInit, WalletClientModule, WalletOperationMeta,
+    WalletOperationMetaVariant, WithdrawState,
+};
 use futures_util::StreamExt;
 use lightning_invoice::Bolt11Invoice;
+use tokio::sync::OwnedMutexGuard;
 
 use crate::{
-    db::{FederationConfig, FederationConfigKey, FederationConfigKeyPrefix},
+    db::{
+        Contact, ContactKey, ContactKeyPrefix, FederationConfig, FederationConfigKey,
+        FederationConfigKeyPrefix, NextContactIdKey, OperationAnnotation,
+        OperationAnnotationKey, OperationContactKey, OperationContactKeyPrefix, PinAttemptsKey,
+        SettingsKey, SettingsRecord,
+    },
+    error::CarbineError,
+    limits::{pin_delay, LimitScope, PinHash, PinPurpose, SpendingLimits, CONFIRMATION_WINDOW},
+    meta::{FederationMeta, META_FIELDS},
+    migrations::run_migrations,
+    operations::{OperationKind, OperationOutcome, OperationRecord, PendingOperation},
+    price::{quote_rate, FiatRate, PriceSource},
+    settings::Settings,
     FederationSelector,
 };
 
+pub(crate) const DEFAULT_EXPIRY_TIME_SECS: u32 = 86400;
+
+/// Number of most recent operations scanned for unfinished ones.
+const PENDING_SCAN_LIMIT: usize = 100;
+
+/// Number of most recent operations shown in a federation's history.
+pub(crate) const HISTORY_LIMIT: usize = 200;
+
+/// Number of operations read at once when reading a whole operation log.
+const OPERATION_PAGE_SIZE: usize = 500;
+
+/// Whether a joined federation's client can currently be used.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FederationStatus {
+    Loading,
+    Ready,
+    /// Usable, but some functionality is unavailable for the given reason.
+    Degraded(String),
+    Failed(LoadFailure),
+    Recovering { complete: u32, total: u32 },
+}
+
+/// Why a federation's client could not be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LoadFailure {
+    /// The guardians could not be reached, loading is retried.
+    Offline(String),
+    /// The stored config or client database can not be used, retrying would
+    /// not help.
+    Config(String),
+    /// Recovering the funds from a seed failed.
+    Recovery(String),
+}
+
+impl LoadFailure {
+    pub fn reason(&self) -> &str {
+        match self {
+            LoadFailure::Offline(reason)
+            | LoadFailure::Config(reason)
+            | LoadFailure::Recovery(reason) => reason,
+        }
+    }
+}
+
+impl FederationStatus {
+    pub fn is_usable(&self) -> bool {
+        matches!(self, FederationStatus::Ready | FederationStatus::Degraded(_))
+    }
+
+    /// Short form shown next to the federation name.
+    pub fn label(&self) -> String {
+        match self {
+            FederationStatus::Loading => "Loading".to_string(),
+            FederationStatus::Ready => "Ready".to_string(),
+            FederationStatus::Degraded(_) => "Degraded".to_string(),
+            FederationStatus::Failed(LoadFailure::Offline(_)) => "Offline".to_string(),
+            FederationStatus::Failed(LoadFailure::Config(_)) => "Config error".to_string(),
+            FederationStatus::Failed(LoadFailure::Recovery(_)) => "Recovery failed".to_string(),
+            FederationStatus::Recovering { .. } => {
+                format!("Recovering {}%", self.recovery_percent())
+            }
+        }
+    }
+
+    pub fn recovery_percent(&self) -> u32 {
+        match self {
+            FederationStatus::Recovering { complete, total } if *total > 0 => {
+                (complete * 100 / total).min(100)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Class suffix used to color the status, e.g. `status-ready`.
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            FederationStatus::Loading => "status-loading",
+            FederationStatus::Ready => "status-ready",
+            FederationStatus::Degraded(_) => "status-degraded",
+            FederationStatus::Failed(_) => "status-failed",
+            FederationStatus::Recovering { .. } => "status-recovering",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct Multimint {
     db: Database,
     mnemonic: Mnemonic,
     modules: ClientModuleInitRegistry,
     clients: BTreeMap<FederationId, ClientHandleArc>,
+    statuses: BTreeMap<FederationId, FederationStatus>,
+    /// When the PIN was last entered to confirm a payment, see `authenticate`.
+    authenticated_at: Option<SystemTime>,
+    /// Held from checking a payment against the limits until it was started,
+    /// so concurrent payments are counted against each other. Shared by all
+    /// snapshots.
+    payment_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl Multimint {
-    pub async fn new() -> anyhow::Result<Self> {
+    /// Opens the wallet database and migrates it to the current schema. Clients
+    /// are not loaded here, see `federation_configs` and `load_client`.
+    pub async fn new() -> Result<Self, CarbineError> {
         // TODO: Need android-safe path here
-        let db: Database = RocksDb::open("client.db").await?.into();
+        let db: Database = RocksDb::open("client.db")
+            .await
+            .map_err(|e| CarbineError::Database(e.to_string()))?
+            .into();
+        run_migrations(&db).await?;
 
         let mnemonic =
             if let Ok(entropy) = Client::load_decodable_client_secret::<Vec<u8>>(&db).await {
-                Mnemonic::from_entropy(&entropy)?
+                Mnemonic::from_entropy(&entropy)
+                    .map_err(|e| CarbineError::Database(format!("Invalid stored seed: {e}")))?
             } else {
                 let mnemonic = Bip39RootSecretStrategy::<12>::random(&mut thread_rng());
 
-                Client::store_encodable_client_secret(&db, mnemonic.to_entropy()).await?;
+                Client::store_encodable_client_secret(&db, mnemonic.to_entropy())
+                    .await
+                    .map_err(|e| CarbineError::Database(e.to_string()))?;
                 mnemonic
             };
 
@@ -60,65 +192,80 @@ impl Multimint {
         modules.attach(WalletClientInit::default());
         modules.attach(fedimint_lnv2_client::LightningClientInit::default());
 
-        let mut multimint = Self {
+        Ok(Self {
             db,
             mnemonic,
             modules,
             clients: BTreeMap::new(),
-        };
-        multimint.load_clients().await?;
-        Ok(multimint)
+            statuses: BTreeMap::new(),
+            authenticated_at: None,
+            payment_lock: Arc::default(),
+        })
     }
 
-    async fn load_clients(&mut self) -> anyhow::Result<()> {
+    pub(crate) async fn federation_configs(&self) -> BTreeMap<FederationId, FederationConfig> {
         let mut dbtx = self.db.begin_transaction_nc().await;
-        let configs = dbtx
-            .find_by_prefix(&FederationConfigKeyPrefix)
+        dbtx.find_by_prefix(&FederationConfigKeyPrefix)
             .await
-            .collect::<BTreeMap<FederationConfigKey, FederationConfig>>()
-            .await;
-        for (id, config) in configs {
-            let client = self
-                .build_client(&id.id, &config.invite_code, config.connector)
-                .await?;
-            self.clients.insert(id.id, client);
-        }
+            .map(|(id, config)| (id.id, config))
+            .collect::<BTreeMap<_, _>>()
+            .await
+    }
 
-        Ok(())
+    /// Opens the client of an already joined federation. Only needs `&self` so
+    /// clients can be loaded concurrently and registered with `add_client`.
+    pub(crate) async fn load_client(
+        &self,
+        federation_id: &FederationId,
+        config: &FederationConfig,
+    ) -> Result<ClientHandleArc, CarbineError> {
+        self.build_client(federation_id, &config.invite_code, config.connector)
+            .await
     }
 
+    pub(crate) fn statuses(&self) -> &BTreeMap<FederationId, FederationStatus> {
+        &self.statuses
+    }
+
+    pub(crate) fn set_status(&mut self, federation_id: FederationId, status: FederationStatus) {
+        self.statuses.insert(federation_id, status);
+    }
+
+    /// Joins the federation by building its client and persisting its config.
+    /// Only needs `&self` so the network I/O can run outside the wallet service,
+    /// which then registers the client with `add_client`.
     // TODO: Implement recovery
-    pub async fn join_federation(
-        &mut self,
-        invite_code: String,
-    ) -> anyhow::Result<FederationSelector> {
-        let invite_code = InviteCode::from_str(&invite_code)?;
+    pub(crate) async fn prepare_join(
+        &self,
+        invite_code: InviteCode,
+    ) -> Result<(FederationSelector, ClientHandleArc), CarbineError> {
         let federation_id = invite_code.federation_id();
         if self.has_federation(&federation_id).await {
-            bail!("Already joined federation")
+            return Err(CarbineError::AlreadyJoined(federation_id));
         }
 
+        let connector = self.settings().await?.connector;
         let client = self
-            .build_client(&federation_id, &invite_code, Connector::Tcp)
+            .build_client(&federation_id, &invite_code, connector)
             .await?;
 
-        let client_config = Connector::default()
+        let client_config = connector
             .download_from_invite_code(&invite_code)
-            .await?;
+            .await
+            .map_err(|e| CarbineError::Network(e.to_string()))?;
         let federation_name = client_config
             .global
             .federation_name()
-            .expect("No federation name")
+            .ok_or(CarbineError::MissingFederationName)?
             .to_owned();
+        let meta = FederationMeta::from_fields(&client_config.global.meta);
         let federation_config = FederationConfig {
             invite_code,
-            connector: Connector::default(),
+            connector,
             federation_name: federation_name.clone(),
+            meta: meta.clone(),
         };
 
-        self.clients.insert(federation_id, client);
-        info!("Added client to internal map");
-
         let mut dbtx = self.db.begin_transaction().await;
         dbtx.insert_new_entry(
             &FederationConfigKey { id: federation_id },
@@ -127,10 +274,88 @@ impl Multimint {
         .await;
         dbtx.commit_tx().await;
 
-        Ok(FederationSelector {
-            federation_name,
-            federation_id,
-        })
+        Ok((
+            FederationSelector {
+                federation_name,
+                federation_id,
+                meta,
+            },
+            client,
+        ))
+    }
+
+    /// Reads the federation's meta again and caches it if it changed. The
+    /// client keeps the meta up to date in the background, the meta in its
+    /// config is used for fields it has not fetched. Returns whether the
+    /// cached meta changed.
+    pub(crate) async fn refresh_meta(
+        &self,
+        federation_id: &FederationId,
+        client: &ClientHandleArc,
+    ) -> bool {
+        let mut fields = client.config().await.global.meta;
+        for key in META_FIELDS {
+            let value = client
+                .meta_service()
+                .get_field::<serde_json::Value>(client.db(), key)
+                .await
+                .and_then(|value| value.value);
+            match value {
+                Some(serde_json::Value::String(value)) => {
+                    fields.insert(key.to_string(), value);
+                }
+                Some(value) => {
+                    fields.insert(key.to_string(), value.to_string());
+                }
+                None => {}
+            }
+        }
+        let meta = FederationMeta::from_fields(&fields);
+
+        let key = FederationConfigKey { id: *federation_id };
+        let mut dbtx = self.db.begin_transaction().await;
+        let Some(mut config) = dbtx.get_value(&key).await else {
+            return false;
+        };
+        if config.meta == meta {
+            return false;
+        }
+        config.meta = meta;
+        dbtx.insert_entry(&key, &config).await;
+        dbtx.commit_tx().await;
+        true
+    }
+
+    pub(crate) fn add_client(&mut self, federation_id: FederationId, client: ClientHandleArc) {
+        let status = Self::client_status(&client);
+        self.clients.insert(federation_id, client);
+        self.statuses.insert(federation_id, status);
+        info!("Added client to internal map");
+    }
+
+    /// Status of a freshly opened client, based on pending recoveries and the
+    /// modules the federation offers.
+    pub(crate) fn client_status(client: &ClientHandleArc) -> FederationStatus {
+        if client.has_pending_recoveries() {
+            return FederationStatus::Recovering {
+                complete: 0,
+                total: 0,
+            };
+        }
+
+        let missing = [
+            (Self::lnv2(client).is_err(), "Lightning"),
+            (Self::mint(client).is_err(), "ecash"),
+            (Self::wallet(client).is_err(), "on-chain"),
+        ]
+        .into_iter()
+        .filter_map(|(missing, name)| missing.then_some(name))
+        .collect::<Vec<_>>();
+        if missing.is_empty() {
+            FederationStatus::Ready
+        } else {
+            FederationStatus::Degraded(format!("{} not supported", missing.join(", ")))
+        }
     }
 
     async fn has_federation(&self, federation_id: &FederationId) -> bool {
@@ -145,14 +370,16 @@ impl Multimint {
         federation_id: &FederationId,
         invite_code: &InviteCode,
         connector: Connector,
-    ) -> anyhow::Result<ClientHandleArc> {
+    ) -> Result<ClientHandleArc, CarbineError> {
         info!("Getting client database...");
         let client_db = self.get_client_database(&federation_id);
         info!("Deriving secret...");
         let secret = Self::derive_federation_secret(&self.mnemonic, &federation_id);
 
         info!("Creating builder...");
-        let mut client_builder = Client::builder(client_db).await?;
+        let mut client_builder = Client::builder(client_db)
+            .await
+            .map_err(|e| CarbineError::Database(e.to_string()))?;
         client_builder.with_module_inits(self.modules.clone());
         client_builder.with_primary_module_kind(fedimint_mint_client::KIND);
         info!("Created builder");
@@ -162,13 +389,207 @@ impl Multimint {
             client_builder.open(secret).await
         } else {
             info!("Downloading client config...");
-            let client_config = connector.download_from_invite_code(&invite_code).await?;
+            let client_config = connector
+                .download_from_invite_code(&invite_code)
+                .await
+                .map_err(|e| CarbineError::Network(e.to_string()))?;
             info!("Creating client by joining...");
             client_builder
                 .join(secret, client_config.clone(), invite_code.api_secret())
                 .await
         }
         .map(Arc::new)
+        .map_err(|e| {
+            if is_connectivity_error(&e) {
+                CarbineError::Network(e.to_string())
+            } else {
+                CarbineError::Client(e.to_string())
+            }
+        })
+    }
+
+    fn client(&self, federation_id: &FederationId) -> Result<&ClientHandleArc, CarbineError> {
+        match (self.clients.get(federation_id), self.statuses.get(federation_id)) {
+            (Some(client), Some(status)) if status.is_usable() => Ok(client),
+            (_, Some(status)) => Err(CarbineError::FederationUnavailable(status.clone())),
+            (_, None) => Err(CarbineError::UnknownFederation(*federation_id)),
+        }
+    }
+
+    fn lnv2(
+        client: &ClientHandleArc,
+    ) -> Result<ClientModuleInstance<'_, fedimint_lnv2_client::LightningClientModule>, CarbineError>
+    {
+        client
+            .get_first_module::<fedimint_lnv2_client::LightningClientModule>()
+            .map_err(|_| CarbineError::ModuleMissing("Lightning payments"))
+    }
+
+    fn mint(
+        client: &ClientHandleArc,
+    ) -> Result<ClientModuleInstance<'_, MintClientModule>, CarbineError> {
+        client
+            .get_first_module::<MintClientModule>()
+            .map_err(|_| CarbineError::ModuleMissing("ecash"))
+    }
+
+    fn wallet(
+        client: &ClientHandleArc,
+    ) -> Result<ClientModuleInstance<'_, WalletClientModule>, CarbineError> {
+        client
+            .get_first_module::<WalletClientModule>()
+            .map_err(|_| CarbineError::ModuleMissing("on-chain deposits and withdrawals"))
+    }
+
+    /// Fails with `InsufficientFunds` if the balance does not cover `needed`.
+    async fn ensure_balance(
+        client: &ClientHandleArc,
+        needed: Amount,
+    ) -> Result<(), CarbineError> {
+        let available = client.get_balance().await;
+        if available < needed {
+            return Err(CarbineError::InsufficientFunds { needed, available });
+        }
+        Ok(())
+    }
+
+    /// Refuses a payment of `amount` out of `federation_id` that exceeds the
+    /// user's limits, or that needs the PIN and it was not entered recently.
+    /// Hold on to the returned guard until the payment was started, so other
+    /// payments wait and then count it.
+    async fn enforce_limits(
+        &self,
+        federation_id: &FederationId,
+        amount: Amount,
+    ) -> Result<OwnedMutexGuard<()>, CarbineError> {
+        let guard = self.payment_lock.clone().lock_owned().await;
+        let settings = self.settings().await?;
+        let federation_limits = settings.federation_limits.get(federation_id);
+
+        let now = fedimint_core::time::now();
+        let longest_window = [federation_limits, Some(&settings.limits)]
+            .into_iter()
+            .flatten()
+            .filter_map(SpendingLimits::longest_window)
+            .max();
+        let spends = match longest_window {
+            Some(window) => self.spends_since(now - window).await,
+            None => Vec::new(),
+        };
+        if let Some(limits) = federation_limits {
+            let federation_spends = spends
+                .iter()
+                .filter(|(id, _, _)| id == federation_id)
+                .map(|(_, at, spent)| (*at, *spent))
+                .collect::<Vec<_>>();
+            limits.check(LimitScope::Federation, amount, &federation_spends, now)?;
+        }
+        let all_spends = spends
+            .iter()
+            .map(|(_, at, spent)| (*at, *spent))
+            .collect::<Vec<_>>();
+        settings
+            .limits
+            .check(LimitScope::Global, amount, &all_spends, now)?;
+
+        let confirmed = self.authenticated_at.is_some_and(|at| {
+            now.duration_since(at)
+                .is_ok_and(|elapsed| elapsed < CONFIRMATION_WINDOW)
+        });
+        let needs_confirmation = settings.pin.is_some()
+            && settings
+                .confirm_above
+                .is_some_and(|threshold| amount > threshold);
+        if needs_confirmation && !confirmed {
+            return Err(CarbineError::ConfirmationRequired(amount));
+        }
+        Ok(guard)
+    }
+
+    /// Payments out of the loaded federations since `since`, with the time
+    /// they were started and what they spent, see `OperationRecord::spent`.
+    async fn spends_since(&self, since: SystemTime) -> Vec<(FederationId, SystemTime, Amount)> {
+        let mut spends = Vec::new();
+        for federation_id in self.clients.keys() {
+            let Ok(client) = self.client(federation_id) else {
+                continue;
+            };
+            let mut last_key = None;
+            loop {
+                let page = client
+                    .operation_log()
+                    .paginate_operations_rev(OPERATION_PAGE_SIZE, last_key)
+                    .await;
+                // Pages are newest first, so older pages are outside the window
+                let complete = page.len() < OPERATION_PAGE_SIZE
+                    || page.last().is_none_or(|(key, _)| key.creation_time < since);
+                last_key = page.last().map(|(key, _)| key.clone());
+                spends.extend(
+                    page.into_iter()
+                        .filter(|(key, _)| key.creation_time >= since)
+                        .filter_map(|(key, entry)| Self::record(federation_id, key, &entry))
+                        .map(|record| (*federation_id, record.started_at, record.spent()))
+                        .filter(|(_, _, spent)| *spent > Amount::ZERO),
+                );
+                if complete {
+                    break;
+                }
+            }
+        }
+        spends
+    }
+
+    /// Checks `pin` against the one in the settings. Once it matched for
+    /// `ConfirmPayment`, payments above the confirmation threshold go through
+    /// for `CONFIRMATION_WINDOW`. After a few wrong PINs, further attempts
+    /// are refused for a while, see `pin_delay`.
+    pub(crate) async fn authenticate(
+        &mut self,
+        pin: &str,
+        purpose: PinPurpose,
+    ) -> Result<(), CarbineError> {
+        let mut settings = self.settings().await?;
+        let Some(hash) = settings.pin.clone() else {
+            return Ok(());
+        };
+
+        let now = fedimint_core::time::now();
+        let now_secs = now
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut attempts = dbtx.get_value(&PinAttemptsKey).await.unwrap_or_default();
+        let next_attempt = attempts.last_failure + pin_delay(attempts.failures).as_secs();
+        if now_secs < next_attempt {
+            return Err(CarbineError::PinDelayed(Duration::from_secs(
+                next_attempt - now_secs,
+            )));
+        }
+
+        let verified = hash.verify(pin);
+        if verified {
+            dbtx.remove_entry(&PinAttemptsKey).await;
+        } else {
+            attempts.failures = attempts.failures.saturating_add(1);
+            attempts.last_failure = now_secs;
+            dbtx.insert_entry(&PinAttemptsKey, &attempts).await;
+        }
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| CarbineError::Database(e.to_string()))?;
+        if !verified {
+            return Err(CarbineError::WrongPin);
+        }
+
+        if hash.is_outdated() {
+            settings.pin = Some(PinHash::new(pin));
+            self.save_settings(&settings).await?;
+        }
+        if purpose == PinPurpose::ConfirmPayment {
+            self.authenticated_at = Some(now);
+        }
+        Ok(())
     }
 
     fn get_client_database(&self, federation_id: &FederationId) -> Database {
@@ -190,93 +611,968 @@ impl Multimint {
         federation_wallet_root_secret.child_key(ChildId(0))
     }
 
+    /// All joined federations, whether or not their client has been loaded.
+    /// See `statuses` for which ones can be used.
     pub(crate) async fn federations(&self) -> Vec<FederationSelector> {
-        let mut dbtx = self.db.begin_transaction_nc().await;
-        dbtx.find_by_prefix(&FederationConfigKeyPrefix)
+        self.federation_configs()
             .await
-            .map(|(id, config)| FederationSelector {
+            .into_iter()
+            .map(|(federation_id, config)| FederationSelector {
                 federation_name: config.federation_name,
-                federation_id: id.id,
+                federation_id,
+                meta: config.meta,
             })
-            .collect::<Vec<_>>()
-            .await
+            .collect()
     }
 
-    pub(crate) async fn balance(&self, federation_id: &FederationId) -> Amount {
-        let client = self
-            .clients
-            .get(federation_id)
-            .expect("No federation exists");
-        client.get_balance().await
+    pub(crate) async fn balance(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<Amount, CarbineError> {
+        let client = self.client(federation_id)?;
+        Ok(client.get_balance().await)
     }
 
+    /// Creates an lnv2 invoice for `amount`. The description is either a memo
+    /// shown to the payer or, for LNURL, the hash of the metadata it commits to.
     pub(crate) async fn receive(
         &self,
         federation_id: &FederationId,
         amount: Amount,
-    ) -> anyhow::Result<(String, OperationId)> {
-        let client = self
-            .clients
-            .get(federation_id)
-            .expect("No federation exists");
-        let lnv2 = client.get_first_module::<fedimint_lnv2_client::LightningClientModule>()?;
-        const DEFAULT_EXPIRY_TIME_SECS: u32 = 86400;
-        let (invoice, operation_id) = lnv2
-            .receive(
-                amount,
-                DEFAULT_EXPIRY_TIME_SECS,
-                Bolt11InvoiceDescription::Direct(String::new()),
-                None,
-                ().into(),
-            )
-            .await?;
-
-        Ok((invoice.to_string(), operation_id))
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+    ) -> Result<(String, OperationId), CarbineError> {
+        let client = self.client(federation_id)?;
+        let lnv2 = Self::lnv2(client)?;
+        let mut last_error = None;
+        for gateway in self.gateway_choices().await? {
+            match lnv2
+                .receive(amount, expiry_secs, description.clone(), gateway, ().into())
+                .await
+            {
+                Ok((invoice, operation_id)) => return Ok((invoice.to_string(), operation_id)),
+                // Another gateway may work, anything else would fail with it too
+                Err(e) => match receive_error(e) {
+                    CarbineError::Gateway(reason) => last_error = Some(reason),
+                    e => return Err(e),
+                },
+            }
+        }
+        Err(CarbineError::Gateway(last_error.unwrap_or_default()))
     }
 
+    /// Pays a BOLT11 invoice through the first of `gateway_choices` that
+    /// accepts it. The balance must cover the fee of the gateway used.
     pub(crate) async fn send(
         &self,
         federation_id: &FederationId,
         invoice: String,
-    ) -> anyhow::Result<OperationId> {
-        let client = self
-            .clients
-            .get(federation_id)
-            .expect("No federation exists");
-        let lnv2 = client.get_first_module::<fedimint_lnv2_client::LightningClientModule>()?;
-        let invoice = Bolt11Invoice::from_str(&invoice)?;
-        let operation_id = lnv2.send(invoice, None, ().into()).await?;
-        Ok(operation_id)
+    ) -> Result<OperationId, CarbineError> {
+        let client = self.client(federation_id)?;
+        let invoice = Bolt11Invoice::from_str(invoice.trim())
+            .map_err(|e| CarbineError::InvalidInvoice(e.to_string()))?;
+        if invoice.is_expired() {
+            return Err(CarbineError::InvalidInvoice("the invoice has expired".to_string()));
+        }
+        let Some(invoice_msats) = invoice.amount_milli_satoshis() else {
+            let reason = self.amountless_unsupported_reason(federation_id)?;
+            return Err(CarbineError::AmountlessInvoice(reason));
+        };
+        let amount = Amount::from_msats(invoice_msats);
+        Self::ensure_balance(client, amount).await?;
+        let _payment_slot = self.enforce_limits(federation_id, amount).await?;
+
+        let lnv2 = Self::lnv2(client)?;
+        let mut last_error = None;
+        for gateway in self.gateway_choices().await? {
+            let sent = match Self::send_total(&lnv2, gateway.as_ref(), &invoice).await {
+                Ok(total) => match Self::ensure_balance(client, total).await {
+                    Ok(()) => lnv2
+                        .send(invoice.clone(), gateway, ().into())
+                        .await
+                        .map_err(send_error),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(operation_id) => return Ok(operation_id),
+                // Another gateway may work, anything else would fail with it too
+                Err(CarbineError::Gateway(reason)) => last_error = Some(reason),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(CarbineError::Gateway(last_error.unwrap_or_default()))
+    }
+
+    /// The amount of `invoice` plus the fee `gateway` charges to pay it, or
+    /// the gateway the client selects if `None`.
+    async fn send_total(
+        lnv2: &fedimint_lnv2_client::LightningClientModule,
+        gateway: Option<&SafeUrl>,
+        invoice: &Bolt11Invoice,
+    ) -> Result<Amount, CarbineError> {
+        let routing_info = match gateway {
+            Some(gateway) => lnv2
+                .routing_info(gateway)
+                .await
+                .map_err(|e| CarbineError::Gateway(e.to_string()))?
+                .ok_or_else(|| {
+                    CarbineError::Gateway(format!("{gateway} does not serve this federation"))
+                })?,
+            None => {
+                lnv2.select_gateway(Some(invoice.clone()))
+                    .await
+                    .map_err(select_gateway_error)?
+                    .1
+            }
+        };
+        let (fee, _) = routing_info.send_parameters(invoice);
+        Ok(fee.add_to(invoice.amount_milli_satoshis().unwrap_or_default()))
+    }
+
+    /// The user's preferred gateways in order, then `None` to let the client
+    /// select one of the federation's gateways.
+    async fn gateway_choices(&self) -> Result<Vec<Option<SafeUrl>>, CarbineError> {
+        Ok(self
+            .settings()
+            .await?
+            .preferred_gateways
+            .into_iter()
+            .map(Some)
+            .chain([None])
+            .collect())
+    }
+
+    /// Both Lightning modules derive the outgoing contract from the invoice
+    /// amount, so neither can pay amountless invoices. Explains this in terms
+    /// of the modules the federation actually offers.
+    pub(crate) fn amountless_unsupported_reason(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<String, CarbineError> {
+        let client = self.client(federation_id)?;
+        let has_lnv2 = client
+            .get_first_module::<fedimint_lnv2_client::LightningClientModule>()
+            .is_ok();
+        let has_lnv1 = client
+            .get_first_module::<fedimint_ln_client::LightningClientModule>()
+            .is_ok();
+        let reason = match (has_lnv2, has_lnv1) {
+            (false, false) => "This federation does not offer Lightning payments.",
+            (true, _) => {
+                "This invoice has no amount, but this federation's Lightning gateways can only \
+                 pay invoices that include one. Ask the recipient for an invoice with an amount."
+            }
+            (false, true) => {
+                "This invoice has no amount, but this federation only offers the legacy \
+                 Lightning module, which can only pay invoices that include one. Ask the \
+                 recipient for an invoice with an amount."
+            }
+        };
+        Ok(reason.to_string())
     }
 
     pub(crate) async fn await_send(
         &self,
         federation_id: &FederationId,
         operation_id: OperationId,
-    ) -> anyhow::Result<FinalSendOperationState> {
-        let client = self
-            .clients
-            .get(federation_id)
-            .expect("No federation exists");
-        let lnv2 = client.get_first_module::<fedimint_lnv2_client::LightningClientModule>()?;
-        let final_state = lnv2.await_final_send_operation_state(operation_id).await?;
-
-        Ok(final_state)
+    ) -> Result<FinalSendOperationState, CarbineError> {
+        let client = self.client(federation_id)?;
+        let lnv2 = Self::lnv2(client)?;
+        lnv2.await_final_send_operation_state(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))
     }
 
     pub(crate) async fn await_receive(
         &self,
         federation_id: &FederationId,
         operation_id: OperationId,
-    ) -> anyhow::Result<FinalReceiveOperationState> {
-        let client = self
-            .clients
-            .get(federation_id)
-            .expect("No federation exists");
-        let lnv2 = client.get_first_module::<fedimint_lnv2_client::LightningClientModule>()?;
-        let final_state = lnv2
-            .await_final_receive_operation_state(operation_id)
-            .await?;
-        Ok(final_state)
+    ) -> Result<FinalReceiveOperationState, CarbineError> {
+        let client = self.client(federation_id)?;
+        let lnv2 = Self::lnv2(client)?;
+        lnv2.await_final_receive_operation_state(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))
+    }
+
+    /// Finds the joined federation that issued the given ecash notes.
+    pub(crate) fn federation_for_notes(&self, notes: &OOBNotes) -> Option<FederationId> {
+        self.clients
+            .keys()
+            .find(|federation_id| federation_id.to_prefix() == notes.federation_id_prefix())
+            .copied()
+    }
+
+    pub(crate) async fn redeem_ecash(
+        &self,
+        federation_id: &FederationId,
+        notes: OOBNotes,
+    ) -> Result<OperationId, CarbineError> {
+        let client = self.client(federation_id)?;
+        let mint = Self::mint(client)?;
+        mint.reissue_external_notes(notes, ())
+            .await
+            .map_err(|e| CarbineError::OperationFailed(format!("Could not redeem ecash: {e}")))
+    }
+
+    pub(crate) async fn await_redeem(
+        &self,
+        federation_id: &FederationId,
+        operation_id: OperationId,
+    ) -> Result<(), CarbineError> {
+        let client = self.client(federation_id)?;
+        let mint = Self::mint(client)?;
+        let mut updates = mint
+            .subscribe_reissue_external_notes(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))?
+            .into_stream();
+        while let Some(update) = updates.next().await {
+            match update {
+                ReissueExternalNotesState::Done => return Ok(()),
+                ReissueExternalNotesState::Failed(e) => {
+                    return Err(CarbineError::OperationFailed(format!(
+                        "Redeeming ecash failed: {e}"
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Err(CarbineError::OperationFailed(
+            "Redeem operation ended without a final state".to_string(),
+        ))
+    }
+
+    pub(crate) async fn spend_ecash(
+        &self,
+        federation_id: &FederationId,
+        amount: Amount,
+    ) -> Result<(OperationId, OOBNotes), CarbineError> {
+        let client = self.client(federation_id)?;
+        Self::ensure_balance(client, amount).await?;
+        let _payment_slot = self.enforce_limits(federation_id, amount).await?;
+        let mint = Self::mint(client)?;
+        // Unclaimed notes are reclaimed automatically after a day
+        const SPEND_TIMEOUT: Duration = Duration::from_secs(60 * 60 * 24);
+        mint.spend_notes_with_selector(
+            &SelectNotesWithAtleastAmount,
+            amount,
+            SPEND_TIMEOUT,
+            false,
+            (),
+        )
+        .await
+        .map_err(|e| CarbineError::OperationFailed(format!("Could not spend ecash: {e}")))
+    }
+
+    pub(crate) async fn deposit_address(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<(OperationId, Address), CarbineError> {
+        let client = self.client(federation_id)?;
+        let wallet = Self::wallet(client)?;
+        let (operation_id, address, _) = wallet
+            .safe_allocate_deposit_address(())
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))?;
+        Ok((operation_id, address))
+    }
+
+    /// The invite code the federation was joined with.
+    pub(crate) async fn invite_code(&self, federation_id: &FederationId) -> Option<InviteCode> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        dbtx.get_value(&FederationConfigKey { id: *federation_id })
+            .await
+            .map(|config| config.invite_code)
+    }
+
+    /// An invite code built from the guardian endpoints in the federation's
+    /// current config, listing enough guardians that one of them is honest.
+    /// `None` if the client is not usable, or if joining needs the API secret
+    /// of the stored invite code, which the config does not carry.
+    pub(crate) async fn fresh_invite_code(
+        &self,
+        federation_id: &FederationId,
+    ) -> Option<InviteCode> {
+        let client = self.client(federation_id).ok()?;
+        if self.invite_code(federation_id).await?.api_secret().is_some() {
+            return None;
+        }
+        let endpoints = client
+            .config()
+            .await
+            .global
+            .api_endpoints
+            .into_iter()
+            .map(|(peer, endpoint)| (peer, endpoint.url))
+            .collect::<BTreeMap<_, _>>();
+        Some(InviteCode::new_with_essential_num_guardians(&endpoints, *federation_id))
+    }
+
+    /// Nostr keys of the guardians of each loaded federation, as hex x-only
+    /// keys. Federations that do not publish broadcast keys are left out.
+    pub(crate) async fn guardian_keys(&self) -> BTreeMap<FederationId, BTreeSet<String>> {
+        let mut keys = BTreeMap::new();
+        for (federation_id, client) in &self.clients {
+            let Some(broadcast_keys) = client.config().await.global.broadcast_public_keys else {
+                continue;
+            };
+            let guardians = broadcast_keys
+                .values()
+                .map(|key| key.x_only_public_key().0.to_string())
+                .collect();
+            keys.insert(*federation_id, guardians);
+        }
+        keys
+    }
+
+    pub(crate) async fn withdraw_fees(
+        &self,
+        federation_id: &FederationId,
+        address: Address<NetworkUnchecked>,
+        amount: fedimint_core::bitcoin::Amount,
+    ) -> Result<PegOutFees, CarbineError> {
+        let client = self.client(federation_id)?;
+        let wallet = Self::wallet(client)?;
+        let address = address
+            .require_network(wallet.get_network())
+            .map_err(|e| CarbineError::InvalidAddress(e.to_string()))?;
+        wallet
+            .get_withdraw_fees(&address, amount)
+            .await
+            .map_err(|e| {
+                if is_connectivity_error(&e) {
+                    CarbineError::Network(e.to_string())
+                } else {
+                    CarbineError::OperationFailed(format!("Could not get withdrawal fees: {e}"))
+                }
+            })
+    }
+
+    pub(crate) async fn withdraw(
+        &self,
+        federation_id: &FederationId,
+        address: Address<NetworkUnchecked>,
+        amount: fedimint_core::bitcoin::Amount,
+        fees: PegOutFees,
+    ) -> Result<OperationId, CarbineError> {
+        let client = self.client(federation_id)?;
+        let needed = Amount::from_sats(amount.to_sat() + fees.amount().to_sat());
+        Self::ensure_balance(client, needed).await?;
+        let _payment_slot = self.enforce_limits(federation_id, needed).await?;
+        let wallet = Self::wallet(client)?;
+        let address = address
+            .require_network(wallet.get_network())
+            .map_err(|e| CarbineError::InvalidAddress(e.to_string()))?;
+        wallet
+            .withdraw(&address, amount, fees, ())
+            .await
+            .map_err(|e| CarbineError::OperationFailed(format!("Could not withdraw: {e}")))
+    }
+
+    pub(crate) async fn await_withdraw(
+        &self,
+        federation_id: &FederationId,
+        operation_id: OperationId,
+    ) -> Result<Txid, CarbineError> {
+        let client = self.client(federation_id)?;
+        let wallet = Self::wallet(client)?;
+        let mut updates = wallet
+            .subscribe_withdraw_updates(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))?
+            .into_stream();
+        while let Some(update) = updates.next().await {
+            match update {
+                WithdrawState::Succeeded(txid) => return Ok(txid),
+                WithdrawState::Failed(e) => {
+                    return Err(CarbineError::OperationFailed(format!("Withdrawal failed: {e}")))
+                }
+                WithdrawState::Created => {}
+            }
+        }
+        Err(CarbineError::OperationFailed(
+            "Withdraw operation ended without a final state".to_string(),
+        ))
+    }
+
+    /// The `limit` most recent operations of a federation, newest first.
+    pub(crate) async fn operations(
+        &self,
+        federation_id: &FederationId,
+        limit: usize,
+    ) -> Result<Vec<OperationRecord>, CarbineError> {
+        let client = self.client(federation_id)?;
+        let entries = client
+            .operation_log()
+            .paginate_operations_rev(limit, None)
+            .await;
+        Ok(self.records(federation_id, entries).await)
+    }
+
+    /// Every operation of a federation, newest first.
+    pub(crate) async fn all_operations(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<Vec<OperationRecord>, CarbineError> {
+        let client = self.client(federation_id)?;
+        let mut entries = Vec::new();
+        let mut last_key = None;
+        loop {
+            let page = client
+                .operation_log()
+                .paginate_operations_rev(OPERATION_PAGE_SIZE, last_key)
+                .await;
+            let complete = page.len() < OPERATION_PAGE_SIZE;
+            last_key = page.last().map(|(key, _)| key.clone());
+            entries.extend(page);
+            if complete || last_key.is_none() {
+                break;
+            }
+        }
+        Ok(self.records(federation_id, entries).await)
+    }
+
+    /// The operation in `entry` as far as the operation log knows it, without
+    /// its counterparty and annotation. `None` for operations we don't show.
+    fn record(
+        federation_id: &FederationId,
+        key: ChronologicalOperationLogKey,
+        entry: &OperationLogEntry,
+    ) -> Option<OperationRecord> {
+        let kind = Self::operation_kind(entry)?;
+        let mut record = OperationRecord {
+            federation_id: *federation_id,
+            operation_id: key.operation_id,
+            kind,
+            started_at: key.creation_time,
+            amount: None,
+            fee: None,
+            reference: None,
+            outcome: Self::recorded_outcome(kind, entry),
+            counterparty: None,
+            annotation: OperationAnnotation::default(),
+        };
+        Self::add_details(&mut record, entry);
+        Some(record)
+    }
+
+    /// Turns operation log entries into records, skipping operations carbine
+    /// does not know, and adds the contacts and annotations we stored.
+    async fn records(
+        &self,
+        federation_id: &FederationId,
+        entries: Vec<(ChronologicalOperationLogKey, OperationLogEntry)>,
+    ) -> Vec<OperationRecord> {
+        let mut records = entries
+            .into_iter()
+            .filter_map(|(key, entry)| Self::record(federation_id, key, &entry))
+            .collect::<Vec<_>>();
+
+        let contacts = self.contacts().await;
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        for record in &mut records {
+            let key = OperationContactKey {
+                operation_id: record.operation_id,
+            };
+            record.counterparty = dbtx
+                .get_value(&key)
+                .await
+                .and_then(|contact| contacts.get(&contact.id))
+                .map(|contact| contact.name.clone());
+            let key = OperationAnnotationKey {
+                operation_id: record.operation_id,
+            };
+            record.annotation = dbtx.get_value(&key).await.unwrap_or_default();
+        }
+        records
+    }
+
+    /// Looks up an operation in the recent history of every usable federation.
+    pub(crate) async fn operation(&self, operation_id: OperationId) -> Option<OperationRecord> {
+        for federation_id in self.clients.keys() {
+            let Ok(operations) = self.operations(federation_id, HISTORY_LIMIT).await else {
+                continue;
+            };
+            if let Some(record) = operations
+                .into_iter()
+                .find(|record| record.operation_id == operation_id)
+            {
+                return Some(record);
+            }
+        }
+        None
+    }
+
+    /// Lists the unfinished operations among the most recent ones in the
+    /// federation's operation log.
+    pub(crate) async fn pending_operations(
+        &self,
+        federation_id: &FederationId,
+    ) -> Result<Vec<PendingOperation>, CarbineError> {
+        Ok(self
+            .operations(federation_id, PENDING_SCAN_LIMIT)
+            .await?
+            .iter()
+            .filter_map(OperationRecord::pending)
+            .collect())
+    }
+
+    fn operation_kind(entry: &OperationLogEntry) -> Option<OperationKind> {
+        let module_kind = entry.operation_module_kind();
+        if module_kind == fedimint_lnv2_client::KIND.as_str() {
+            match entry.meta::<LightningOperationMeta>() {
+                LightningOperationMeta::Send(_) => Some(OperationKind::LightningSend),
+                LightningOperationMeta::Receive(_) => Some(OperationKind::LightningReceive),
+            }
+        } else if module_kind == fedimint_mint_client::KIND.as_str() {
+            match entry.meta::<MintOperationMeta>().variant {
+                MintOperationMetaVariant::Reissuance { .. } => Some(OperationKind::EcashRedeem),
+                MintOperationMetaVariant::SpendOOB { .. } => Some(OperationKind::EcashSpend),
+            }
+        } else if module_kind == fedimint_wallet_client::KIND.as_str() {
+            match entry.meta::<WalletOperationMeta>().variant {
+                WalletOperationMetaVariant::Deposit { .. } => Some(OperationKind::Deposit),
+                WalletOperationMetaVariant::Withdraw { .. } => Some(OperationKind::Withdraw),
+                WalletOperationMetaVariant::RbfWithdraw { .. } => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Fills in the amount, fee and payment hash or transaction id of a record
+    /// from the operation's meta and final state.
+    fn add_details(record: &mut OperationRecord, entry: &OperationLogEntry) {
+        match record.kind {
+            OperationKind::LightningSend | OperationKind::LightningReceive => {
+                match entry.meta::<LightningOperationMeta>() {
+                    LightningOperationMeta::Send(meta) => {
+                        // The contract amount includes the gateway fee, the
+                        // invoice amount is what the payee receives
+                        let LightningInvoice::Bolt11(invoice) = &meta.invoice;
+                        let paid = invoice.amount_milli_satoshis().map(Amount::from_msats);
+                        record.amount = Some(paid.unwrap_or(meta.contract.amount));
+                        record.fee = paid.map(|paid| meta.contract.amount.saturating_sub(paid));
+                        record.reference = Some(invoice.payment_hash().to_string());
+                    }
+                    LightningOperationMeta::Receive(meta) => {
+                        let LightningInvoice::Bolt11(invoice) = &meta.invoice;
+                        let received = meta.contract.commitment.amount;
+                        record.amount = Some(received);
+                        record.fee = invoice
+                            .amount_milli_satoshis()
+                            .map(|msats| Amount::from_msats(msats).saturating_sub(received));
+                        record.reference = Some(invoice.payment_hash().to_string());
+                    }
+                }
+            }
+            OperationKind::EcashRedeem | OperationKind::EcashSpend => {
+                record.amount = Some(entry.meta::<MintOperationMeta>().amount);
+            }
+            OperationKind::Deposit => {
+                if let Some(DepositStateV2::Claimed {
+                    btc_deposited,
+                    btc_out_point,
+                }) = entry.outcome::<DepositStateV2>()
+                {
+                    record.amount = Some(Amount::from_sats(btc_deposited.to_sat()));
+                    record.reference = Some(btc_out_point.txid.to_string());
+                }
+            }
+            OperationKind::Withdraw => {
+                if let WalletOperationMetaVariant::Withdraw { amount, fee, .. } =
+                    entry.meta::<WalletOperationMeta>().variant
+                {
+                    record.amount = Some(Amount::from_sats(amount.to_sat()));
+                    record.fee = Some(Amount::from_sats(fee.amount().to_sat()));
+                }
+                if let Some(WithdrawState::Succeeded(txid)) = entry.outcome::<WithdrawState>() {
+                    record.reference = Some(txid.to_string());
+                }
+            }
+        }
+    }
+
+    /// How a finished operation ended, read from the final state the client
+    /// stored in its operation log. `None` if it has not finished yet.
+    fn recorded_outcome(
+        kind: OperationKind,
+        entry: &OperationLogEntry,
+    ) -> Option<OperationOutcome> {
+        let unexpected = || OperationOutcome::Failed("it ended in an unexpected state".to_string());
+        let outcome = match kind {
+            OperationKind::LightningSend => match entry.outcome::<SendOperationState>()? {
+                SendOperationState::Success(_) => OperationOutcome::Succeeded,
+                SendOperationState::Refunded => OperationOutcome::Refunded,
+                SendOperationState::Failure => {
+                    OperationOutcome::Failed("the payment could not be completed".to_string())
+                }
+                _ => unexpected(),
+            },
+            OperationKind::LightningReceive => match entry.outcome::<ReceiveOperationState>()? {
+                ReceiveOperationState::Claimed => OperationOutcome::Succeeded,
+                ReceiveOperationState::Expired => OperationOutcome::Expired,
+                ReceiveOperationState::Failure => {
+                    OperationOutcome::Failed("the payment could not be claimed".to_string())
+                }
+                _ => unexpected(),
+            },
+            OperationKind::EcashRedeem => match entry.outcome::<ReissueExternalNotesState>()? {
+                ReissueExternalNotesState::Done => OperationOutcome::Succeeded,
+                ReissueExternalNotesState::Failed(e) => OperationOutcome::Failed(e),
+                _ => unexpected(),
+            },
+            OperationKind::EcashSpend => match entry.outcome::<SpendOOBState>()? {
+                SpendOOBState::Success => OperationOutcome::Succeeded,
+                SpendOOBState::Refunded | SpendOOBState::UserCanceledSuccess => {
+                    OperationOutcome::Refunded
+                }
+                SpendOOBState::UserCanceledFailure => {
+                    OperationOutcome::Failed("the notes could not be reclaimed".to_string())
+                }
+                _ => unexpected(),
+            },
+            OperationKind::Deposit => match entry.outcome::<DepositStateV2>()? {
+                DepositStateV2::Claimed { .. } => OperationOutcome::Succeeded,
+                DepositStateV2::Failed(e) => OperationOutcome::Failed(e),
+                _ => unexpected(),
+            },
+            OperationKind::Withdraw => match entry.outcome::<WithdrawState>()? {
+                WithdrawState::Succeeded(_) => OperationOutcome::Succeeded,
+                WithdrawState::Failed(e) => OperationOutcome::Failed(e),
+                _ => unexpected(),
+            },
+        };
+        Some(outcome)
+    }
+
+    /// Waits for a pending operation to finish. Failures of the operation
+    /// itself are an outcome, errors mean it could not be followed.
+    pub(crate) async fn await_operation(
+        &self,
+        operation: &PendingOperation,
+    ) -> Result<OperationOutcome, CarbineError> {
+        let federation_id = &operation.federation_id;
+        let operation_id = operation.operation_id;
+        let outcome = match operation.kind {
+            OperationKind::LightningSend => {
+                match self.await_send(federation_id, operation_id).await? {
+                    FinalSendOperationState::Success => OperationOutcome::Succeeded,
+                    FinalSendOperationState::Refunded => OperationOutcome::Refunded,
+                    FinalSendOperationState::Failure => OperationOutcome::Failed(
+                        "the payment could not be completed".to_string(),
+                    ),
+                }
+            }
+            OperationKind::LightningReceive => {
+                match self.await_receive(federation_id, operation_id).await? {
+                    FinalReceiveOperationState::Claimed => OperationOutcome::Succeeded,
+                    FinalReceiveOperationState::Expired => OperationOutcome::Expired,
+                    FinalReceiveOperationState::Failure => {
+                        OperationOutcome::Failed("the payment could not be claimed".to_string())
+                    }
+                }
+            }
+            OperationKind::EcashRedeem => {
+                Self::outcome(self.await_redeem(federation_id, operation_id).await)?
+            }
+            OperationKind::EcashSpend => self.await_spend(federation_id, operation_id).await?,
+            OperationKind::Deposit => {
+                Self::outcome(self.await_deposit(federation_id, operation_id).await)?
+            }
+            OperationKind::Withdraw => Self::outcome(
+                self.await_withdraw(federation_id, operation_id)
+                    .await
+                    .map(|_| ()),
+            )?,
+        };
+        Ok(outcome)
+    }
+
+    /// Treats the failure of an operation as its outcome.
+    fn outcome(result: Result<(), CarbineError>) -> Result<OperationOutcome, CarbineError> {
+        match result {
+            Ok(()) => Ok(OperationOutcome::Succeeded),
+            Err(CarbineError::OperationFailed(reason)) => Ok(OperationOutcome::Failed(reason)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn await_spend(
+        &self,
+        federation_id: &FederationId,
+        operation_id: OperationId,
+    ) -> Result<OperationOutcome, CarbineError> {
+        let client = self.client(federation_id)?;
+        let mint = Self::mint(client)?;
+        let mut updates = mint
+            .subscribe_spend_notes(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))?
+            .into_stream();
+        while let Some(update) = updates.next().await {
+            match update {
+                SpendOOBState::Success => return Ok(OperationOutcome::Succeeded),
+                SpendOOBState::Refunded | SpendOOBState::UserCanceledSuccess => {
+                    return Ok(OperationOutcome::Refunded)
+                }
+                SpendOOBState::UserCanceledFailure => {
+                    return Ok(OperationOutcome::Failed(
+                        "the notes could not be reclaimed".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Err(CarbineError::OperationFailed(
+            "Spend operation ended without a final state".to_string(),
+        ))
+    }
+
+    async fn await_deposit(
+        &self,
+        federation_id: &FederationId,
+        operation_id: OperationId,
+    ) -> Result<(), CarbineError> {
+        let client = self.client(federation_id)?;
+        let wallet = Self::wallet(client)?;
+        let mut updates = wallet
+            .subscribe_deposit(operation_id)
+            .await
+            .map_err(|e| CarbineError::OperationFailed(e.to_string()))?
+            .into_stream();
+        while let Some(update) = updates.next().await {
+            match update {
+                DepositStateV2::Claimed { .. } => return Ok(()),
+                DepositStateV2::Failed(e) => {
+                    return Err(CarbineError::OperationFailed(format!("Deposit failed: {e}")))
+                }
+                _ => {}
+            }
+        }
+        Err(CarbineError::OperationFailed(
+            "Deposit operation ended without a final state".to_string(),
+        ))
+    }
+
+    /// Quotes the bitcoin price in `currency` from `source`, see `quote_rate`.
+    pub(crate) async fn fiat_rate(
+        &self,
+        source: &PriceSource,
+        currency: &str,
+    ) -> Option<FiatRate> {
+        quote_rate(&self.db, source, currency).await
+    }
+
+    /// Reads the user's settings, the defaults if none were saved yet. Fails
+    /// if the record can not be read rather than dropping the user's limits
+    /// and PIN, until the settings are saved again.
+    pub(crate) async fn settings(&self) -> Result<Settings, CarbineError> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let Some(record) = dbtx.get_value(&SettingsKey).await else {
+            return Ok(Settings::default());
+        };
+        serde_json::from_str(&record.json)
+            .map_err(|e| CarbineError::SettingsUnreadable(e.to_string()))
+    }
+
+    pub(crate) async fn save_settings(&self, settings: &Settings) -> Result<(), CarbineError> {
+        let json =
+            serde_json::to_string(settings).map_err(|e| CarbineError::Database(e.to_string()))?;
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&SettingsKey, &SettingsRecord { json }).await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| CarbineError::Database(e.to_string()))
+    }
+
+    pub(crate) async fn contacts(&self) -> BTreeMap<u64, Contact> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        dbtx.find_by_prefix(&ContactKeyPrefix)
+            .await
+            .map(|(key, contact)| (key.id, contact))
+            .collect::<BTreeMap<_, _>>()
+            .await
+    }
+
+    pub(crate) async fn contact(&self, id: u64) -> Option<Contact> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        dbtx.get_value(&ContactKey { id }).await
+    }
+
+    /// Stores `contact` under `id`, or under a new id if `id` is `None`.
+    /// Returns the id it was stored under.
+    pub(crate) async fn save_contact(&self, id: Option<u64>, contact: &Contact) -> u64 {
+        let mut dbtx = self.db.begin_transaction().await;
+        let id = match id {
+            Some(id) => id,
+            None => Self::next_contact_id(&mut dbtx.to_ref_nc()).await,
+        };
+        dbtx.insert_entry(&ContactKey { id }, contact).await;
+        dbtx.commit_tx().await;
+        id
+    }
+
+    /// Takes the id for a new contact. Wallets from before `NextContactIdKey`
+    /// start past every id a contact or a linked operation used.
+    async fn next_contact_id(dbtx: &mut DatabaseTransaction<'_>) -> u64 {
+        let id = match dbtx.get_value(&NextContactIdKey).await {
+            Some(id) => id,
+            None => {
+                let contacts = dbtx
+                    .find_by_prefix(&ContactKeyPrefix)
+                    .await
+                    .map(|(key, _)| key.id + 1)
+                    .fold(0, |max, id| async move { max.max(id) })
+                    .await;
+                let linked = dbtx
+                    .find_by_prefix(&OperationContactKeyPrefix)
+                    .await
+                    .map(|(_, contact)| contact.id + 1)
+                    .fold(0, |max, id| async move { max.max(id) })
+                    .await;
+                contacts.max(linked)
+            }
+        };
+        dbtx.insert_entry(&NextContactIdKey, &(id + 1)).await;
+        id
+    }
+
+    pub(crate) async fn delete_contact(&self, id: u64) {
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.remove_entry(&ContactKey { id }).await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Associates an operation with the contact `payee` belongs to, if any.
+    pub(crate) async fn link_contact(&self, payee: &str, operation_id: OperationId) {
+        let contacts = self.contacts().await;
+        let Some(id) = contacts
+            .into_iter()
+            .find(|(_, contact)| contact.pays_to(payee))
+            .map(|(id, _)| id)
+        else {
+            return;
+        };
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&OperationContactKey { operation_id }, &ContactKey { id })
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Stores what the user wrote about an operation, removing it if empty.
+    pub(crate) async fn annotate(
+        &self,
+        operation_id: OperationId,
+        annotation: &OperationAnnotation,
+    ) {
+        let key = OperationAnnotationKey { operation_id };
+        let mut dbtx = self.db.begin_transaction().await;
+        if annotation.is_empty() {
+            dbtx.remove_entry(&key).await;
+        } else {
+            dbtx.insert_entry(&key, annotation).await;
+        }
+        dbtx.commit_tx().await;
+    }
+}
+
+fn send_error(e: SendPaymentError) -> CarbineError {
+    match e {
+        SendPaymentError::InvoiceMissingAmount
+        | SendPaymentError::InvoiceExpired
+        | SendPaymentError::WrongCurrency { .. } => CarbineError::InvalidInvoice(e.to_string()),
+        SendPaymentError::PendingPreviousPayment(_)
+        | SendPaymentError::SuccessfulPreviousPayment(_) => CarbineError::AlreadyPaid,
+        SendPaymentError::FailedToSelectGateway(e) => select_gateway_error(e),
+        SendPaymentError::FederationError(_) => CarbineError::Network(e.to_string()),
+        SendPaymentError::FinalizationError(_) => {
+            CarbineError::OperationFailed(format!("Could not fund the payment: {e}"))
+        }
+        // The gateway is unreachable, does not serve the federation or its terms are too expensive
+        _ => CarbineError::Gateway(e.to_string()),
+    }
+}
+
+fn receive_error(e: ReceiveError) -> CarbineError {
+    match e {
+        ReceiveError::FailedToSelectGateway(e) => select_gateway_error(e),
+        ReceiveError::DustAmount => CarbineError::AmountTooSmall,
+        // The gateway is unreachable, does not serve the federation, is too
+        // expensive or returned an unusable invoice
+        _ => CarbineError::Gateway(e.to_string()),
+    }
+}
+
+fn select_gateway_error(e: SelectGatewayError) -> CarbineError {
+    match e {
+        SelectGatewayError::FederationError(_) => CarbineError::Network(e.to_string()),
+        SelectGatewayError::NoVettedGateways => CarbineError::NoGateway,
+        SelectGatewayError::FailedToFetchRoutingInfo => CarbineError::Gateway(e.to_string()),
+    }
+}
+
+/// Whether `e` was caused by failing to reach the federation rather than by
+/// something retrying would not fix.
+pub(crate) fn is_connectivity_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.is::<FederationError>() || cause.is::<PeerError>() || cause.is::<std::io::Error>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::db::mem_impl::MemDatabase;
+
+    use super::*;
+
+    fn database() -> Database {
+        Database::new(MemDatabase::new(), Default::default())
+    }
+
+    async fn add_contact(db: &Database) -> u64 {
+        let mut dbtx = db.begin_transaction().await;
+        let id = Multimint::next_contact_id(&mut dbtx.to_ref_nc()).await;
+        dbtx.insert_entry(&ContactKey { id }, &Contact::default())
+            .await;
+        dbtx.commit_tx().await;
+        id
+    }
+
+    #[tokio::test]
+    async fn never_reuses_contact_ids() {
+        let db = database();
+        assert_eq!(add_contact(&db).await, 0);
+        let newest = add_contact(&db).await;
+        assert_eq!(newest, 1);
+
+        // Delete the newest contact after paying it, as `delete_contact` does
+        let mut dbtx = db.begin_transaction().await;
+        let operation_id = OperationId([1; 32]);
+        dbtx.insert_entry(&OperationContactKey { operation_id }, &ContactKey { id: newest })
+            .await;
+        dbtx.remove_entry(&ContactKey { id: newest }).await;
+        dbtx.commit_tx().await;
+
+        assert_eq!(add_contact(&db).await, 2);
+    }
+
+    #[tokio::test]
+    async fn starts_contact_ids_past_old_links() {
+        // A wallet from before the counter, whose contact 4 was deleted
+        let db = database();
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&ContactKey { id: 0 }, &Contact::default())
+            .await;
+        let operation_id = OperationId([1; 32]);
+        dbtx.insert_entry(&OperationContactKey { operation_id }, &ContactKey { id: 4 })
+            .await;
+        dbtx.commit_tx().await;
+
+        assert_eq!(add_contact(&db).await, 5);
+        assert_eq!(add_contact(&db).await, 6);
     }
 }
@@ -0,0 +1,635 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use fedimint_core::{
+    bitcoin::hashes::{sha256, Hash},
+    config::FederationId,
+    invite_code::InviteCode,
+    secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey},
+    task::timeout,
+};
+use futures_util::{future::join_all, SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tokio_tungstenite::{connect_async, tungstenite, MaybeTlsStream, WebSocketStream};
+
+use crate::db::Contact;
+
+/// Relays queried when the user has not configured any.
+pub(crate) const DEFAULT_RELAYS: [&str; 3] =
+    ["wss://relay.damus.io", "wss://nos.lol", "wss://relay.primal.net"];
+
+/// Kind of the events federations are announced with, see NIP-87.
+const FEDERATION_ANNOUNCEMENT: u64 = 38173;
+/// Kind of the events users recommend an announced service with.
+const RECOMMENDATION: u64 = 38000;
+
+/// How long a relay may take to connect and send the stored events.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+const EVENT_LIMIT: u64 = 500;
+const SUBSCRIPTION_ID: &str = "carbine-discovery";
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A federation announced on Nostr, merged from all announcements and
+/// recommendations that refer to it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DiscoveredFederation {
+    pub federation_id: FederationId,
+    pub name: Option<String>,
+    pub network: Option<String>,
+    pub modules: BTreeSet<String>,
+    /// Invite codes advertised for the federation, checked to belong to it.
+    pub invite_codes: BTreeSet<String>,
+    /// Number of distinct users recommending the federation, anyone can
+    /// publish these so they do not affect the order.
+    pub recommendations: usize,
+    /// Names of our contacts among them.
+    pub recommended_by: BTreeSet<String>,
+}
+
+impl DiscoveredFederation {
+    fn new(federation_id: FederationId) -> Self {
+        DiscoveredFederation {
+            federation_id,
+            name: None,
+            network: None,
+            modules: BTreeSet::new(),
+            invite_codes: BTreeSet::new(),
+            recommendations: 0,
+            recommended_by: BTreeSet::new(),
+        }
+    }
+
+    /// Name to show, the shortened federation id if none was announced.
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("Federation {}", &self.federation_id.to_string()[..8]))
+    }
+}
+
+/// A Nostr event as defined by NIP-01.
+#[derive(Clone, Debug, Deserialize)]
+struct Event {
+    id: String,
+    pubkey: String,
+    created_at: u64,
+    kind: u64,
+    tags: Vec<Vec<String>>,
+    content: String,
+    sig: String,
+}
+
+impl Event {
+    /// Checks that the id is the hash of the event and that its author
+    /// signed it, relays are not trusted to do so.
+    fn verify(&self) -> bool {
+        let serialized = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ])
+        .to_string();
+        let id = sha256::Hash::hash(serialized.as_bytes());
+        if id.to_string() != self.id.to_lowercase() {
+            return false;
+        }
+        let (Ok(pubkey), Ok(signature)) = (
+            XOnlyPublicKey::from_str(&self.pubkey),
+            schnorr::Signature::from_str(&self.sig),
+        ) else {
+            return false;
+        };
+        let message = Message::from_digest(id.to_byte_array());
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &message, &pubkey)
+            .is_ok()
+    }
+
+    /// Values of all tags called `name`.
+    fn tag_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.tags
+            .iter()
+            .filter(move |tag| tag.first().is_some_and(|tag_name| tag_name == name))
+            .filter_map(|tag| tag.get(1).map(String::as_str))
+    }
+
+    fn tag_value(&self, name: &str) -> Option<&str> {
+        self.tag_values(name).next()
+    }
+
+    /// The federation the event is about, from its `d` tag.
+    fn federation_id(&self) -> Option<FederationId> {
+        FederationId::from_str(self.tag_value("d")?).ok()
+    }
+}
+
+/// Asks `relays` for federation announcements and recommendations. Relays
+/// that fail are skipped, an error is only returned if all of them failed.
+/// `guardian_keys` are the known Nostr keys of each federation's guardians,
+/// see `merge_events`.
+pub(crate) async fn discover_federations(
+    relays: &[String],
+    contacts: &BTreeMap<u64, Contact>,
+    guardian_keys: &BTreeMap<FederationId, BTreeSet<String>>,
+) -> anyhow::Result<Vec<DiscoveredFederation>> {
+    let filters = [
+        json!({ "kinds": [FEDERATION_ANNOUNCEMENT], "limit": EVENT_LIMIT }),
+        json!({
+            "kinds": [RECOMMENDATION],
+            "#k": [FEDERATION_ANNOUNCEMENT.to_string()],
+            "limit": EVENT_LIMIT
+        }),
+    ];
+    let results = join_all(relays.iter().map(|relay| query_relay(relay, &filters))).await;
+
+    let mut events = BTreeMap::new();
+    let mut last_error = None;
+    for result in results {
+        match result {
+            Ok(relay_events) => {
+                for event in relay_events {
+                    events.insert(event.id.clone(), event);
+                }
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    if events.is_empty() {
+        if let Some(e) = last_error {
+            return Err(e.context("No relay could be queried"));
+        }
+    }
+
+    Ok(merge_events(events.into_values().collect(), contacts, guardian_keys))
+}
+
+/// Sends a subscription with `filters` to `relay` and collects the verified
+/// events it has stored.
+async fn query_relay(relay: &str, filters: &[serde_json::Value]) -> anyhow::Result<Vec<Event>> {
+    let (mut socket, _) = timeout(RELAY_TIMEOUT, connect_async(relay))
+        .await
+        .with_context(|| format!("{relay} did not answer in time"))?
+        .with_context(|| format!("Could not connect to {relay}"))?;
+
+    let mut request = vec![json!("REQ"), json!(SUBSCRIPTION_ID)];
+    request.extend(filters.iter().cloned());
+    socket
+        .send(tungstenite::Message::text(serde_json::to_string(&request)?))
+        .await?;
+
+    // Relays that never send EOSE still get to deliver what they have
+    let mut events = Vec::new();
+    let read = timeout(RELAY_TIMEOUT, read_events(&mut socket, &mut events)).await;
+    let close = json!(["CLOSE", SUBSCRIPTION_ID]).to_string();
+    let _ = socket.send(tungstenite::Message::text(close)).await;
+    let _ = socket.close(None).await;
+    if let Ok(Err(e)) = read {
+        if events.is_empty() {
+            return Err(e.context(format!("{relay} failed")));
+        }
+    }
+    Ok(events)
+}
+
+/// Reads relay messages into `events` until the relay has sent all stored
+/// events or closed the subscription.
+async fn read_events(socket: &mut Socket, events: &mut Vec<Event>) -> anyhow::Result<()> {
+    while let Some(message) = socket.next().await {
+        let tungstenite::Message::Text(text) = message? else {
+            continue;
+        };
+        let Ok(message) = serde_json::from_str::<Vec<serde_json::Value>>(text.as_str()) else {
+            continue;
+        };
+        match message.first().and_then(|kind| kind.as_str()) {
+            Some("EVENT") => {
+                let event = message
+                    .get(2)
+                    .and_then(|event| serde_json::from_value::<Event>(event.clone()).ok());
+                if let Some(event) = event.filter(Event::verify) {
+                    events.push(event);
+                }
+            }
+            Some("EOSE") => return Ok(()),
+            Some("CLOSED") => {
+                let reason = message.get(2).and_then(|reason| reason.as_str());
+                bail!("Relay closed the subscription: {}", reason.unwrap_or_default());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Combines announcements and recommendations into one entry per federation,
+/// those recommended by more contacts first. Names are only taken from
+/// announcements by the federation's guardians or by contacts, as anyone can
+/// announce any federation under a misleading name.
+fn merge_events(
+    mut events: Vec<Event>,
+    contacts: &BTreeMap<u64, Contact>,
+    guardian_keys: &BTreeMap<FederationId, BTreeSet<String>>,
+) -> Vec<DiscoveredFederation> {
+    let contact_names = contacts
+        .values()
+        .flat_map(|contact| {
+            contact
+                .identifiers
+                .iter()
+                .map(String::as_str)
+                .filter_map(nostr_pubkey)
+                .map(|pubkey| (pubkey, contact.name.clone()))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // Newest first, so the latest version of a replaceable event wins
+    events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+    let mut federations = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+    for event in events {
+        let Some(federation_id) = event.federation_id() else {
+            continue;
+        };
+        if !seen.insert((event.kind, event.pubkey.clone(), federation_id)) {
+            continue;
+        }
+        let federation = federations
+            .entry(federation_id)
+            .or_insert_with(|| DiscoveredFederation::new(federation_id));
+
+        federation.invite_codes.extend(
+            event
+                .tag_values("u")
+                .filter(|code| {
+                    InviteCode::from_str(code)
+                        .is_ok_and(|invite| invite.federation_id() == federation_id)
+                })
+                .map(str::to_owned),
+        );
+        if federation.network.is_none() {
+            federation.network = event.tag_value("n").map(str::to_owned);
+        }
+        match event.kind {
+            FEDERATION_ANNOUNCEMENT => {
+                let trusted = contact_names.contains_key(&event.pubkey)
+                    || guardian_keys
+                        .get(&federation_id)
+                        .is_some_and(|keys| keys.contains(&event.pubkey));
+                if trusted && federation.name.is_none() {
+                    federation.name = announced_name(&event.content);
+                }
+                federation.modules.extend(
+                    event
+                        .tag_values("modules")
+                        .flat_map(|modules| modules.split(','))
+                        .map(|module| module.trim().to_string())
+                        .filter(|module| !module.is_empty()),
+                );
+            }
+            RECOMMENDATION => {
+                federation.recommendations += 1;
+                if let Some(name) = contact_names.get(&event.pubkey) {
+                    federation.recommended_by.insert(name.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut federations = federations
+        .into_values()
+        .filter(|federation| !federation.invite_codes.is_empty())
+        .collect::<Vec<_>>();
+    federations.sort_by_key(|federation| {
+        (
+            std::cmp::Reverse(federation.recommended_by.len()),
+            federation.display_name().to_lowercase(),
+        )
+    });
+    federations
+}
+
+/// The `name` in an announcement's metadata content, if it has one.
+fn announced_name(content: &str) -> Option<String> {
+    let metadata = serde_json::from_str::<serde_json::Value>(content).ok()?;
+    let name = metadata.get("name")?.as_str()?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Hex public key of a contact identifier that is an `npub` or a hex key.
+fn nostr_pubkey(identifier: &str) -> Option<String> {
+    let identifier = identifier.trim();
+    let identifier = identifier.strip_prefix("nostr:").unwrap_or(identifier);
+    if let Ok((hrp, data)) = bech32::decode(identifier) {
+        if hrp.as_str() != "npub" {
+            return None;
+        }
+        return XOnlyPublicKey::from_slice(&data)
+            .ok()
+            .map(|key| key.to_string());
+    }
+    XOnlyPublicKey::from_str(identifier)
+        .ok()
+        .map(|key| key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::{secp256k1::Keypair, util::SafeUrl, PeerId};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn keypair(seed: u8) -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[seed; 32]).unwrap()
+    }
+
+    fn pubkey(keys: &Keypair) -> String {
+        keys.x_only_public_key().0.to_string()
+    }
+
+    /// An event signed by `keys`, as a relay would send it.
+    fn signed_event(
+        keys: &Keypair,
+        created_at: u64,
+        kind: u64,
+        tags: Vec<Vec<String>>,
+        content: &str,
+    ) -> serde_json::Value {
+        let pubkey = pubkey(keys);
+        let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+        let id = sha256::Hash::hash(serialized.as_bytes());
+        let sig = Secp256k1::new()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(id.to_byte_array()), keys);
+        json!({
+            "id": id.to_string(),
+            "pubkey": pubkey,
+            "created_at": created_at,
+            "kind": kind,
+            "tags": tags,
+            "content": content,
+            "sig": sig.to_string(),
+        })
+    }
+
+    fn event(value: serde_json::Value) -> Event {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn tag(name: &str, value: &str) -> Vec<String> {
+        vec![name.to_string(), value.to_string()]
+    }
+
+    fn federation_id(seed: u8) -> FederationId {
+        FederationId(sha256::Hash::hash(&[seed]))
+    }
+
+    fn invite_code(federation_id: FederationId) -> String {
+        InviteCode::new(
+            SafeUrl::parse("wss://fedimint.example.com/").unwrap(),
+            PeerId::from(0),
+            federation_id,
+            None,
+        )
+        .to_string()
+    }
+
+    fn announcement(
+        keys: &Keypair,
+        created_at: u64,
+        federation_id: FederationId,
+        name: &str,
+    ) -> Event {
+        event(signed_event(
+            keys,
+            created_at,
+            FEDERATION_ANNOUNCEMENT,
+            vec![
+                tag("d", &federation_id.to_string()),
+                tag("u", &invite_code(federation_id)),
+                tag("n", "bitcoin"),
+                tag("modules", "mint, ln,wallet"),
+            ],
+            &json!({ "name": name }).to_string(),
+        ))
+    }
+
+    fn recommendation(keys: &Keypair, federation_id: FederationId) -> Event {
+        event(signed_event(
+            keys,
+            1,
+            RECOMMENDATION,
+            vec![
+                tag("d", &federation_id.to_string()),
+                tag("k", &FEDERATION_ANNOUNCEMENT.to_string()),
+            ],
+            "",
+        ))
+    }
+
+    fn contact(name: &str, keys: &Keypair) -> Contact {
+        Contact {
+            name: name.to_string(),
+            identifiers: vec![format!("nostr:{}", pubkey(keys))],
+            ..Default::default()
+        }
+    }
+
+    /// Serves one subscription like a relay, answering the REQ with
+    /// `replies`. Returns the URL to connect to.
+    async fn stand_in_relay(replies: Vec<serde_json::Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let Some(Ok(tungstenite::Message::Text(request))) = socket.next().await else {
+                panic!("Expected a subscription request");
+            };
+            let request = serde_json::from_str::<Vec<serde_json::Value>>(request.as_str()).unwrap();
+            assert_eq!(request[..2], [json!("REQ"), json!(SUBSCRIPTION_ID)]);
+            for reply in replies {
+                let reply = tungstenite::Message::text(reply.to_string());
+                if socket.send(reply).await.is_err() {
+                    return;
+                }
+            }
+            while let Some(Ok(_)) = socket.next().await {}
+        });
+        url
+    }
+
+    #[test]
+    fn verifies_events() {
+        let keys = keypair(1);
+        let valid = signed_event(&keys, 1_700_000_000, 1, vec![tag("t", "x")], "hello");
+        assert!(event(valid.clone()).verify());
+
+        let mut altered = valid.clone();
+        altered["content"] = json!("goodbye");
+        assert!(!event(altered).verify());
+
+        let mut wrong_author = valid.clone();
+        wrong_author["pubkey"] = json!(pubkey(&keypair(2)));
+        assert!(!event(wrong_author).verify());
+
+        let mut forged = valid.clone();
+        let other = signed_event(&keys, 1_700_000_000, 1, vec![], "other");
+        forged["sig"] = other["sig"].clone();
+        assert!(!event(forged).verify());
+
+        let mut garbage = valid;
+        garbage["sig"] = json!("not a signature");
+        assert!(!event(garbage).verify());
+    }
+
+    #[test]
+    fn names_federations_only_from_trusted_announcements() {
+        let guardian = keypair(1);
+        let friend = keypair(2);
+        let stranger = keypair(3);
+        let guarded = federation_id(1);
+        let befriended = federation_id(2);
+        let unknown = federation_id(3);
+        let contacts = BTreeMap::from([(0, contact("Alice", &friend))]);
+        let guardian_keys = BTreeMap::from([(guarded, BTreeSet::from([pubkey(&guardian)]))]);
+
+        let events = vec![
+            announcement(&guardian, 1, guarded, "Guarded"),
+            announcement(&stranger, 2, guarded, "Impostor"),
+            announcement(&stranger, 3, befriended, "Impostor"),
+            announcement(&friend, 2, befriended, "Befriended"),
+            announcement(&stranger, 1, unknown, "Impostor"),
+            // The guardian's key is no one's for other federations
+            announcement(&guardian, 4, unknown, "Impostor"),
+        ];
+        let merged = merge_events(events, &contacts, &guardian_keys);
+
+        let names = merged
+            .iter()
+            .map(|federation| (federation.federation_id, federation.name.as_deref()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            names,
+            BTreeMap::from([
+                (guarded, Some("Guarded")),
+                (befriended, Some("Befriended")),
+                (unknown, None),
+            ])
+        );
+        let federation = &merged[0];
+        assert_eq!(federation.network.as_deref(), Some("bitcoin"));
+        assert_eq!(
+            federation.modules,
+            BTreeSet::from(["ln", "mint", "wallet"].map(str::to_owned))
+        );
+        assert_eq!(federation.invite_codes.len(), 1);
+    }
+
+    #[test]
+    fn ranks_by_contact_recommendations() {
+        let friend = keypair(1);
+        let strangers = (10..20).map(keypair).collect::<Vec<_>>();
+        let popular = federation_id(1);
+        let recommended = federation_id(2);
+        let contacts = BTreeMap::from([(0, contact("Alice", &friend))]);
+
+        let mut events = vec![
+            announcement(&friend, 1, popular, "A popular one"),
+            announcement(&friend, 1, recommended, "Recommended"),
+            recommendation(&friend, recommended),
+            // Duplicates of one user's recommendation count once
+            recommendation(&friend, recommended),
+        ];
+        events.extend(strangers.iter().map(|keys| recommendation(keys, popular)));
+        let merged = merge_events(events, &contacts, &BTreeMap::new());
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].federation_id, recommended);
+        assert_eq!(merged[0].recommendations, 1);
+        assert_eq!(merged[0].recommended_by, BTreeSet::from(["Alice".to_string()]));
+        assert_eq!(merged[1].federation_id, popular);
+        assert_eq!(merged[1].recommendations, strangers.len());
+        assert!(merged[1].recommended_by.is_empty());
+    }
+
+    #[test]
+    fn skips_federations_without_valid_invite_codes() {
+        let keys = keypair(1);
+        let federation = federation_id(1);
+        let other = federation_id(2);
+        let events = vec![event(signed_event(
+            &keys,
+            1,
+            FEDERATION_ANNOUNCEMENT,
+            vec![
+                tag("d", &federation.to_string()),
+                tag("u", &invite_code(other)),
+                tag("u", "fed11broken"),
+            ],
+            "",
+        ))];
+
+        assert!(merge_events(events, &BTreeMap::new(), &BTreeMap::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn queries_relays_until_eose() {
+        let keys = keypair(1);
+        let valid = signed_event(&keys, 1, 1, vec![], "valid");
+        let mut forged = signed_event(&keys, 2, 1, vec![], "forged");
+        forged["content"] = json!("altered");
+        let late = signed_event(&keys, 3, 1, vec![], "after EOSE");
+        let relay = stand_in_relay(vec![
+            json!(["NOTICE", "hello"]),
+            json!(["EVENT", SUBSCRIPTION_ID, valid]),
+            json!(["EVENT", SUBSCRIPTION_ID, forged]),
+            json!(["EVENT", SUBSCRIPTION_ID, { "not": "an event" }]),
+            json!(["EOSE", SUBSCRIPTION_ID]),
+            json!(["EVENT", SUBSCRIPTION_ID, late]),
+        ])
+        .await;
+
+        let events = query_relay(&relay, &[json!({ "kinds": [1] })]).await.unwrap();
+
+        assert_eq!(
+            events.iter().map(|event| event.content.as_str()).collect::<Vec<_>>(),
+            ["valid"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_closed_subscriptions() {
+        let relay = stand_in_relay(vec![json!(["CLOSED", SUBSCRIPTION_ID, "blocked: spam"])]).await;
+        let error = query_relay(&relay, &[]).await.unwrap_err();
+        assert!(format!("{error:#}").contains("blocked: spam"), "{error:#}");
+
+        // Events sent before the subscription closed are kept
+        let keys = keypair(1);
+        let relay = stand_in_relay(vec![
+            json!(["EVENT", SUBSCRIPTION_ID, signed_event(&keys, 1, 1, vec![], "kept")]),
+            json!(["CLOSED", SUBSCRIPTION_ID, "rate-limited"]),
+        ])
+        .await;
+        let events = query_relay(&relay, &[]).await.unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fails_on_unreachable_relays() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        assert!(query_relay(&relay, &[]).await.is_err());
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(not(feature = "desktop"))]
+use dioxus::logger::tracing::info;
+
+use crate::{
+    operations::{OperationKind, OperationOutcome},
+    service::WalletEvent,
+};
+
+/// A message shown to the user outside of the app window.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+impl Notification {
+    /// The notification for `event`, if it is one the user should be told about
+    /// while not looking at the app.
+    pub fn for_event(event: &WalletEvent, federation_name: &str) -> Option<Self> {
+        let WalletEvent::OperationFinished { operation, outcome } = event;
+        let title = match (operation.kind, outcome) {
+            (OperationKind::LightningReceive, OperationOutcome::Succeeded) => "Payment received",
+            (OperationKind::LightningSend, OperationOutcome::Refunded) => "Payment refunded",
+            (OperationKind::LightningSend, OperationOutcome::Failed(_)) => "Payment failed",
+            (OperationKind::Deposit, OperationOutcome::Succeeded) => "Deposit claimed",
+            (OperationKind::Withdraw, OperationOutcome::Succeeded) => "Withdrawal broadcast",
+            _ => return None,
+        };
+        Some(Notification {
+            title: title.to_string(),
+            body: format!("{federation_name}: {}", outcome.describe(operation.kind)),
+        })
+    }
+}
+
+/// Shows notifications using the platform's notification system.
+pub(crate) trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+/// Sends notifications through the freedesktop notification spec, or the
+/// native equivalent on macOS and Windows.
+#[cfg(feature = "desktop")]
+pub(crate) struct DesktopNotifier;
+
+#[cfg(feature = "desktop")]
+impl Notifier for DesktopNotifier {
+    fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        notify_rust::Notification::new()
+            .appname("carbine")
+            .summary(&notification.title)
+            .body(&notification.body)
+            .show()?;
+        Ok(())
+    }
+}
+
+/// Only logs notifications, for platforms without a `Notifier` yet.
+#[cfg(not(feature = "desktop"))]
+pub(crate) struct LogNotifier;
+
+#[cfg(not(feature = "desktop"))]
+impl Notifier for LogNotifier {
+    fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        info!("{}: {}", notification.title, notification.body);
+        Ok(())
+    }
+}
+
+/// The `Notifier` for the platform carbine was built for.
+pub(crate) fn platform_notifier() -> Box<dyn Notifier> {
+    #[cfg(feature = "desktop")]
+    {
+        Box::new(DesktopNotifier)
+    }
+    #[cfg(not(feature = "desktop"))]
+    {
+        Box::new(LogNotifier)
+    }
+}
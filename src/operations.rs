@@ -0,0 +1,229 @@
+use std::time::SystemTime;
+
+use fedimint_client::OperationId;
+use fedimint_core::{config::FederationId, Amount};
+
+use crate::db::OperationAnnotation;
+
+/// The kinds of operations carbine starts and follows to completion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OperationKind {
+    LightningSend,
+    LightningReceive,
+    EcashRedeem,
+    EcashSpend,
+    Deposit,
+    Withdraw,
+}
+
+impl OperationKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            OperationKind::LightningSend => "Lightning payment",
+            OperationKind::LightningReceive => "Lightning invoice",
+            OperationKind::EcashRedeem => "Ecash redemption",
+            OperationKind::EcashSpend => "Ecash notes",
+            OperationKind::Deposit => "On-chain deposit",
+            OperationKind::Withdraw => "On-chain withdrawal",
+        }
+    }
+
+    /// What an unfinished operation of this kind is waiting for.
+    pub fn waiting_for(&self) -> &'static str {
+        match self {
+            OperationKind::LightningSend => "Waiting for the gateway to pay",
+            OperationKind::LightningReceive => "Waiting for payment",
+            OperationKind::EcashRedeem => "Waiting for the federation",
+            OperationKind::EcashSpend => "Waiting for the recipient to redeem",
+            OperationKind::Deposit => "Waiting for a deposit",
+            OperationKind::Withdraw => "Waiting for broadcast",
+        }
+    }
+}
+
+/// An operation from a client's operation log that has not finished yet.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PendingOperation {
+    pub federation_id: FederationId,
+    pub operation_id: OperationId,
+    pub kind: OperationKind,
+    pub started_at: SystemTime,
+}
+
+/// An operation from a client's operation log, finished or not.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OperationRecord {
+    pub federation_id: FederationId,
+    pub operation_id: OperationId,
+    pub kind: OperationKind,
+    pub started_at: SystemTime,
+    /// Amount moved by the operation, if its kind records one.
+    pub amount: Option<Amount>,
+    /// Fee paid to the gateway or for the on-chain transaction, if known.
+    pub fee: Option<Amount>,
+    /// Payment hash of Lightning payments, transaction id of on-chain ones.
+    pub reference: Option<String>,
+    /// `None` while the operation has not finished.
+    pub outcome: Option<OperationOutcome>,
+    /// Name of the contact the operation was made with.
+    pub counterparty: Option<String>,
+    pub annotation: OperationAnnotation,
+}
+
+impl OperationRecord {
+    /// The operation to follow if it has not finished yet.
+    pub fn pending(&self) -> Option<PendingOperation> {
+        self.outcome.is_none().then(|| PendingOperation {
+            federation_id: self.federation_id,
+            operation_id: self.operation_id,
+            kind: self.kind,
+            started_at: self.started_at,
+        })
+    }
+
+    /// Amount, fees included, the operation took out of the wallet. Zero for
+    /// incoming operations and for outgoing ones whose funds came back.
+    pub fn spent(&self) -> Amount {
+        let outgoing = matches!(
+            self.kind,
+            OperationKind::LightningSend | OperationKind::EcashSpend | OperationKind::Withdraw
+        );
+        let returned = self
+            .outcome
+            .as_ref()
+            .is_some_and(|outcome| *outcome != OperationOutcome::Succeeded);
+        if !outgoing || returned {
+            return Amount::ZERO;
+        }
+        self.amount.unwrap_or(Amount::ZERO) + self.fee.unwrap_or(Amount::ZERO)
+    }
+
+    /// Whether the history search `query` matches the operation.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        let contains = |text: &str| text.to_lowercase().contains(&query);
+        query.is_empty()
+            || self.annotation.matches(&query)
+            || contains(self.kind.label())
+            || self.counterparty.as_deref().is_some_and(contains)
+    }
+
+    /// Current state of the operation, e.g. for the history.
+    pub fn state(&self) -> String {
+        match &self.outcome {
+            None => self.kind.waiting_for().to_string(),
+            Some(OperationOutcome::Succeeded) => "Completed".to_string(),
+            Some(OperationOutcome::Refunded) => "Refunded".to_string(),
+            Some(OperationOutcome::Expired) => "Expired".to_string(),
+            Some(OperationOutcome::Failed(reason)) => format!("Failed: {reason}"),
+        }
+    }
+}
+
+/// How a followed operation ended.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OperationOutcome {
+    Succeeded,
+    /// Funds of a failed outgoing operation were returned to the wallet.
+    Refunded,
+    Expired,
+    Failed(String),
+}
+
+impl OperationOutcome {
+    /// Describes how an operation of `kind` ended, e.g. for a notification.
+    pub fn describe(&self, kind: OperationKind) -> String {
+        match (self, kind) {
+            (OperationOutcome::Succeeded, OperationKind::LightningSend) => {
+                "Lightning payment sent".to_string()
+            }
+            (OperationOutcome::Succeeded, OperationKind::LightningReceive) => {
+                "Lightning payment received".to_string()
+            }
+            (OperationOutcome::Succeeded, OperationKind::EcashRedeem) => {
+                "Ecash redeemed".to_string()
+            }
+            (OperationOutcome::Succeeded, OperationKind::EcashSpend) => {
+                "Ecash notes were redeemed by the recipient".to_string()
+            }
+            (OperationOutcome::Succeeded, OperationKind::Deposit) => {
+                "On-chain deposit claimed".to_string()
+            }
+            (OperationOutcome::Succeeded, OperationKind::Withdraw) => {
+                "On-chain withdrawal broadcast".to_string()
+            }
+            (OperationOutcome::Refunded, kind) => {
+                format!("{} failed and was refunded", kind.label())
+            }
+            (OperationOutcome::Expired, kind) => format!("{} expired", kind.label()),
+            (OperationOutcome::Failed(reason), kind) => {
+                format!("{} failed: {reason}", kind.label())
+            }
+        }
+    }
+}
+
+/// Formats how long ago `time` was, e.g. `5 min ago`.
+pub(crate) fn format_elapsed(time: SystemTime) -> String {
+    let secs = fedimint_core::time::now()
+        .duration_since(time)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn record(kind: OperationKind, amount: u64, fee: Option<u64>) -> OperationRecord {
+        OperationRecord {
+            federation_id: FederationId::dummy(),
+            operation_id: OperationId([0; 32]),
+            kind,
+            started_at: UNIX_EPOCH,
+            amount: Some(Amount::from_sats(amount)),
+            fee: fee.map(Amount::from_sats),
+            reference: None,
+            outcome: Some(OperationOutcome::Succeeded),
+            counterparty: None,
+            annotation: OperationAnnotation::default(),
+        }
+    }
+
+    #[test]
+    fn counts_fees_once() {
+        // A send records the invoice amount and the gateway fee on top of it
+        let send = record(OperationKind::LightningSend, 10_000, Some(30));
+        assert_eq!(send.spent(), Amount::from_sats(10_030));
+
+        // Amountless invoices only know the contract amount, fee included
+        let amountless = record(OperationKind::LightningSend, 10_030, None);
+        assert_eq!(amountless.spent(), Amount::from_sats(10_030));
+
+        let withdraw = record(OperationKind::Withdraw, 50_000, Some(500));
+        assert_eq!(withdraw.spent(), Amount::from_sats(50_500));
+    }
+
+    #[test]
+    fn counts_only_funds_that_left() {
+        let receive = record(OperationKind::LightningReceive, 10_000, Some(30));
+        assert_eq!(receive.spent(), Amount::ZERO);
+
+        let mut refunded = record(OperationKind::LightningSend, 10_000, Some(30));
+        refunded.outcome = Some(OperationOutcome::Refunded);
+        assert_eq!(refunded.spent(), Amount::ZERO);
+
+        // Pending payments count until their funds come back
+        let mut pending = record(OperationKind::EcashSpend, 10_000, None);
+        pending.outcome = None;
+        assert_eq!(pending.spent(), Amount::from_sats(10_000));
+    }
+}
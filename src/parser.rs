@@ -0,0 +1,260 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use fedimint_core::{
+    bitcoin::{address::NetworkUnchecked, Address, Denomination},
+    invite_code::InviteCode,
+};
+use fedimint_mint_client::OOBNotes;
+use lightning_invoice::Bolt11Invoice;
+
+use crate::lnurl::{self, strip_prefix_ignore_case};
+
+/// Everything a user might paste into carbine, classified by the flow that
+/// can handle it.
+#[derive(Debug, Clone)]
+pub(crate) enum PaymentString {
+    Bolt11(Bolt11Invoice),
+    /// A BIP21 `bitcoin:` URI. If it carries a `lightning=` parameter the
+    /// invoice is preferred over the on-chain address.
+    Bip21 {
+        address: Address<NetworkUnchecked>,
+        amount: Option<fedimint_core::bitcoin::Amount>,
+        lightning: Option<Bolt11Invoice>,
+    },
+    OnchainAddress(Address<NetworkUnchecked>),
+    InviteCode(InviteCode),
+    Ecash(OOBNotes),
+    Lnurl(String),
+    LightningAddress(String),
+}
+
+impl PaymentString {
+    /// Short name of the detected type, used to tell the user what we found.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PaymentString::Bolt11(_) => "Lightning invoice",
+            PaymentString::Bip21 { .. } => "Bitcoin payment request",
+            PaymentString::OnchainAddress(_) => "Bitcoin address",
+            PaymentString::InviteCode(_) => "Federation invite code",
+            PaymentString::Ecash(_) => "Ecash",
+            PaymentString::Lnurl(_) => "LNURL",
+            PaymentString::LightningAddress(_) => "Lightning Address",
+        }
+    }
+}
+
+pub(crate) fn parse_payment_string(input: &str) -> anyhow::Result<PaymentString> {
+    let input = input.trim();
+    if input.is_empty() {
+        bail!("Nothing to parse");
+    }
+
+    if let Some(uri) = strip_prefix_ignore_case(input, "bitcoin:") {
+        return parse_bip21(uri);
+    }
+
+    let input = strip_prefix_ignore_case(input, "lightning:").unwrap_or(input);
+
+    if let Ok(invoice) = Bolt11Invoice::from_str(input) {
+        return Ok(PaymentString::Bolt11(invoice));
+    }
+
+    if let Ok(invite_code) = InviteCode::from_str(input) {
+        return Ok(PaymentString::InviteCode(invite_code));
+    }
+
+    if let Ok(notes) = OOBNotes::from_str(input) {
+        return Ok(PaymentString::Ecash(notes));
+    }
+
+    if lnurl::is_lnurl(input) {
+        if input.contains('@') {
+            return Ok(PaymentString::LightningAddress(input.to_owned()));
+        }
+        return Ok(PaymentString::Lnurl(input.to_owned()));
+    }
+
+    if let Ok(address) = Address::from_str(input) {
+        return Ok(PaymentString::OnchainAddress(address));
+    }
+
+    bail!("Unrecognized payment string")
+}
+
+fn parse_bip21(uri: &str) -> anyhow::Result<PaymentString> {
+    let (address, query) = uri.split_once('?').unwrap_or((uri, ""));
+    let address = Address::from_str(address).context("Invalid bitcoin address")?;
+
+    let mut amount = None;
+    let mut lightning = None;
+    for (key, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+        match key.to_ascii_lowercase().as_str() {
+            "amount" => {
+                amount = Some(
+                    fedimint_core::bitcoin::Amount::from_str_in(value, Denomination::Bitcoin)
+                        .context("Invalid amount in bitcoin URI")?,
+                );
+            }
+            "lightning" => {
+                lightning = Some(
+                    Bolt11Invoice::from_str(value)
+                        .context("Invalid lightning invoice in bitcoin URI")?,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    Ok(PaymentString::Bip21 {
+        address,
+        amount,
+        lightning,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::{
+        bitcoin::hashes::{sha256, Hash},
+        config::FederationId,
+        secp256k1::{Secp256k1, SecretKey},
+        util::SafeUrl,
+        PeerId, TieredMulti,
+    };
+    use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+    use super::*;
+
+    const ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+
+    fn invoice(msats: Option<u64>) -> String {
+        let key = SecretKey::from_slice(&[42; 32]).unwrap();
+        let builder = InvoiceBuilder::new(Currency::Bitcoin)
+            .description("Coffee".to_string())
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([2; 32]))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144);
+        match msats {
+            Some(msats) => builder
+                .amount_milli_satoshis(msats)
+                .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key)),
+            None => {
+                builder.build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+            }
+        }
+        .unwrap()
+        .to_string()
+    }
+
+    fn invite_code() -> String {
+        InviteCode::new(
+            SafeUrl::parse("wss://fedimint.example.com/").unwrap(),
+            PeerId::from(0),
+            FederationId::dummy(),
+            None,
+        )
+        .to_string()
+    }
+
+    fn ecash() -> String {
+        OOBNotes::new(FederationId::dummy().to_prefix(), TieredMulti::default()).to_string()
+    }
+
+    fn lnurl() -> String {
+        let hrp = bech32::Hrp::parse("lnurl").unwrap();
+        bech32::encode::<bech32::Bech32>(hrp, b"https://service.com/api?q=1").unwrap()
+    }
+
+    #[test]
+    fn classifies_payment_strings() {
+        let amountless = invoice(None);
+        let invoice = invoice(Some(21_000));
+        let cases = [
+            (invoice.clone(), "Lightning invoice"),
+            (format!("lightning:{invoice}"), "Lightning invoice"),
+            (format!("LIGHTNING:{invoice}"), "Lightning invoice"),
+            (amountless, "Lightning invoice"),
+            (
+                format!("bitcoin:{ADDRESS}?amount=0.001&lightning={invoice}"),
+                "Bitcoin payment request",
+            ),
+            (
+                format!("bitcoin:{ADDRESS}?amount=0.001"),
+                "Bitcoin payment request",
+            ),
+            (
+                format!("BITCOIN:{}", ADDRESS.to_uppercase()),
+                "Bitcoin payment request",
+            ),
+            (ADDRESS.to_string(), "Bitcoin address"),
+            (format!("  {ADDRESS}\n"), "Bitcoin address"),
+            (invite_code(), "Federation invite code"),
+            (ecash(), "Ecash"),
+            (lnurl(), "LNURL"),
+            (format!("lightning:{}", lnurl().to_uppercase()), "LNURL"),
+            ("alice@example.com".to_string(), "Lightning Address"),
+            (
+                "lightning:alice@example.com".to_string(),
+                "Lightning Address",
+            ),
+        ];
+
+        for (input, kind) in cases {
+            let parsed = parse_payment_string(&input)
+                .unwrap_or_else(|e| panic!("Could not parse {input}: {e}"));
+            assert_eq!(parsed.kind(), kind, "{input}");
+        }
+    }
+
+    #[test]
+    fn prefers_lightning_in_bip21() {
+        let invoice = invoice(Some(21_000));
+        let parsed = parse_payment_string(&format!(
+            "bitcoin:{ADDRESS}?amount=0.001&lightning={invoice}"
+        ))
+        .unwrap();
+        let PaymentString::Bip21 {
+            amount, lightning, ..
+        } = parsed
+        else {
+            panic!("Not a BIP21 URI: {parsed:?}");
+        };
+        assert_eq!(
+            amount,
+            Some(fedimint_core::bitcoin::Amount::from_sat(100_000))
+        );
+        assert_eq!(
+            lightning.map(|lightning| lightning.to_string()),
+            Some(invoice)
+        );
+
+        let parsed = parse_payment_string(&format!("bitcoin:{ADDRESS}")).unwrap();
+        assert!(matches!(
+            parsed,
+            PaymentString::Bip21 {
+                amount: None,
+                lightning: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for input in [
+            "",
+            "   ",
+            "hello world",
+            "lnbc1notaninvoice",
+            "bitcoin:",
+            "bitcoin:notanaddress",
+            &format!("bitcoin:{ADDRESS}?amount=lots"),
+            &format!("bitcoin:{ADDRESS}?lightning=lnbc1broken"),
+            "fed11broken",
+        ] {
+            assert!(parse_payment_string(input).is_err(), "{input}");
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{ensure, Context};
+use async_trait::async_trait;
+use dioxus::logger::tracing::warn;
+use fedimint_core::{
+    db::{Database, IDatabaseTransactionOpsCoreTyped},
+    Amount,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{FiatPrice, FiatPriceKey},
+    units::{group_thousands, parse_decimal, AmountParseError},
+};
+
+const MSATS_PER_BTC: u128 = 100_000_000_000;
+
+/// Cached prices older than this are not shown, so a source that stopped
+/// working does not leave us converting at a stale rate.
+pub(crate) const MAX_PRICE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Currencies offered in the UI. The default price source quotes all of them.
+pub(crate) const SUPPORTED_CURRENCIES: [&str; 7] =
+    ["USD", "EUR", "GBP", "CAD", "CHF", "AUD", "JPY"];
+
+/// Source of the current bitcoin price in some fiat currency.
+#[async_trait]
+pub(crate) trait PriceProvider: Send + Sync {
+    /// Returns the price of one bitcoin in cents of `currency`.
+    async fn cents_per_btc(&self, currency: &str) -> anyhow::Result<u64>;
+}
+
+/// Fetches the price from a JSON HTTP endpoint. Both the URL and the JSON
+/// pointer to the price may contain `{currency}` or `{CURRENCY}`, which are
+/// replaced with the lowercase or uppercase currency code.
+pub(crate) struct HttpPriceProvider {
+    pub url: String,
+    pub pointer: String,
+}
+
+#[async_trait]
+impl PriceProvider for HttpPriceProvider {
+    async fn cents_per_btc(&self, currency: &str) -> anyhow::Result<u64> {
+        let url = substitute_currency(&self.url, currency);
+        let pointer = substitute_currency(&self.pointer, currency);
+        let response: serde_json::Value = reqwest::get(&url)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let price = response
+            .pointer(&pointer)
+            .and_then(|price| price.as_f64())
+            .with_context(|| format!("Price source has no price at {pointer}"))?;
+        ensure!(price.is_finite() && price > 0.0, "Price source returned an invalid price");
+        Ok((price * 100.0).round() as u64)
+    }
+}
+
+/// A manually entered exchange rate.
+pub(crate) struct StaticPriceProvider {
+    pub cents_per_btc: u64,
+}
+
+#[async_trait]
+impl PriceProvider for StaticPriceProvider {
+    async fn cents_per_btc(&self, _currency: &str) -> anyhow::Result<u64> {
+        ensure!(self.cents_per_btc > 0, "No exchange rate entered");
+        Ok(self.cents_per_btc)
+    }
+}
+
+/// Decimal places fiat amounts in `currency` are shown and entered with.
+/// Prices are stored in hundredths of the currency either way.
+pub(crate) fn currency_decimals(currency: &str) -> u32 {
+    match currency {
+        "JPY" => 0,
+        _ => 2,
+    }
+}
+
+/// Formats hundredths of `currency` with its decimal places, e.g. `1,234.50`
+/// or `1,235` for JPY.
+pub(crate) fn format_cents(cents: u64, currency: &str) -> String {
+    match currency_decimals(currency) {
+        0 => group_thousands((cents + 50) / 100),
+        _ => format!("{}.{:02}", group_thousands(cents / 100), cents % 100),
+    }
+}
+
+/// Parses an amount of `currency` like `12.50` into hundredths of it.
+pub(crate) fn parse_cents(input: &str, currency: &str) -> Result<u64, AmountParseError> {
+    let decimals = currency_decimals(currency);
+    parse_decimal(input, decimals as usize)?
+        .checked_mul(10u64.pow(2 - decimals))
+        .ok_or(AmountParseError::TooLarge)
+}
+
+fn substitute_currency(template: &str, currency: &str) -> String {
+    template
+        .replace("{currency}", &currency.to_ascii_lowercase())
+        .replace("{CURRENCY}", &currency.to_ascii_uppercase())
+}
+
+/// User configurable choice of `PriceProvider`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum PriceSource {
+    Http { url: String, pointer: String },
+    Static { cents_per_btc: u64 },
+}
+
+impl Default for PriceSource {
+    fn default() -> Self {
+        PriceSource::Http {
+            url: "https://mempool.space/api/v1/prices".to_string(),
+            pointer: "/{CURRENCY}".to_string(),
+        }
+    }
+}
+
+impl PriceSource {
+    pub fn provider(&self) -> Box<dyn PriceProvider> {
+        match self {
+            PriceSource::Http { url, pointer } => Box::new(HttpPriceProvider {
+                url: url.clone(),
+                pointer: pointer.clone(),
+            }),
+            PriceSource::Static { cents_per_btc } => Box::new(StaticPriceProvider {
+                cents_per_btc: *cents_per_btc,
+            }),
+        }
+    }
+}
+
+/// A quoted exchange rate used to convert between amounts and fiat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FiatRate {
+    pub currency: String,
+    pub cents_per_btc: u64,
+    /// Unix timestamp of when the rate was quoted.
+    pub fetched_at: u64,
+}
+
+impl FiatRate {
+    pub fn new(currency: &str, cents_per_btc: u64) -> Self {
+        FiatRate {
+            currency: currency.to_owned(),
+            cents_per_btc,
+            fetched_at: unix_now(),
+        }
+    }
+
+    pub fn from_cached(currency: &str, cached: FiatPrice) -> Self {
+        FiatRate {
+            currency: currency.to_owned(),
+            cents_per_btc: cached.cents_per_btc,
+            fetched_at: cached.fetched_at,
+        }
+    }
+
+    pub fn to_cached(&self) -> FiatPrice {
+        FiatPrice {
+            cents_per_btc: self.cents_per_btc,
+            fetched_at: self.fetched_at,
+        }
+    }
+
+    pub fn to_cents(&self, amount: Amount) -> u64 {
+        (amount.msats as u128 * self.cents_per_btc as u128 / MSATS_PER_BTC) as u64
+    }
+
+    pub fn from_cents(&self, cents: u64) -> Amount {
+        Amount::from_msats((cents as u128 * MSATS_PER_BTC / self.cents_per_btc as u128) as u64)
+    }
+
+    /// How long ago the rate was quoted.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.fetched_at))
+    }
+
+    /// Formats the fiat value of `amount`, e.g. `12.34 USD`.
+    pub fn format(&self, amount: Amount) -> String {
+        format!(
+            "{} {}",
+            format_cents(self.to_cents(amount), &self.currency),
+            self.currency
+        )
+    }
+
+    /// Parses a fiat amount like `12.50` and converts it at this rate.
+    pub fn parse(&self, input: &str) -> anyhow::Result<Amount> {
+        let cents = parse_cents(input, &self.currency)?;
+        ensure!(cents > 0, "Amount must be greater than zero");
+        ensure!(self.cents_per_btc > 0, "No exchange rate available");
+
+        Ok(self.from_cents(cents))
+    }
+}
+
+/// Quotes the bitcoin price in `currency` from `source`, caching it in `db`.
+/// Falls back to the last cached price if the source fails and that price is
+/// not older than `MAX_PRICE_AGE`.
+pub(crate) async fn quote_rate(
+    db: &Database,
+    source: &PriceSource,
+    currency: &str,
+) -> Option<FiatRate> {
+    let key = FiatPriceKey {
+        currency: currency.to_owned(),
+    };
+    match source.provider().cents_per_btc(currency).await {
+        Ok(cents_per_btc) => {
+            let rate = FiatRate::new(currency, cents_per_btc);
+            let mut dbtx = db.begin_transaction().await;
+            dbtx.insert_entry(&key, &rate.to_cached()).await;
+            dbtx.commit_tx().await;
+            Some(rate)
+        }
+        Err(e) => {
+            warn!("Could not fetch {currency} price, using cached price: {e}");
+            let mut dbtx = db.begin_transaction_nc().await;
+            dbtx.get_value(&key)
+                .await
+                .map(|cached| FiatRate::from_cached(currency, cached))
+                .filter(|rate| rate.age() <= MAX_PRICE_AGE)
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    fedimint_core::time::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Stands in for a price API on a local port, answering every request
+    /// with the status and JSON body `respond` returns for its path. Returns
+    /// the base URL.
+    async fn serve(
+        respond: impl Fn(&str) -> (u16, serde_json::Value) + Send + Sync + 'static,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![0; 8192];
+                    let read = stream.read(&mut request).await.unwrap_or_default();
+                    let request = String::from_utf8_lossy(&request[..read]);
+                    let target = request.split_whitespace().nth(1).unwrap_or("/");
+                    let (status, body) = respond(target);
+                    let body = body.to_string();
+                    let response = format!(
+                        "HTTP/1.1 {status} Status\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        url
+    }
+
+    /// A price API quoting `price` for USD only, under a lowercase path and
+    /// an uppercase key.
+    async fn price_api(price: serde_json::Value) -> HttpPriceProvider {
+        let url = serve(move |target| match target {
+            "/prices/usd" => (200, json!({ "data": { "USD": { "last": price } } })),
+            _ => (404, json!({ "error": "unknown currency" })),
+        })
+        .await;
+        HttpPriceProvider {
+            url: format!("{url}/prices/{{currency}}"),
+            pointer: "/data/{CURRENCY}/last".to_string(),
+        }
+    }
+
+    fn database() -> Database {
+        Database::new(MemDatabase::new(), Default::default())
+    }
+
+    async fn cache(db: &Database, currency: &str, cached: FiatPrice) {
+        let mut dbtx = db.begin_transaction().await;
+        let key = FiatPriceKey {
+            currency: currency.to_owned(),
+        };
+        dbtx.insert_entry(&key, &cached).await;
+        dbtx.commit_tx().await;
+    }
+
+    fn rate(currency: &str, cents_per_btc: u64) -> FiatRate {
+        FiatRate {
+            currency: currency.to_owned(),
+            cents_per_btc,
+            fetched_at: 0,
+        }
+    }
+
+    #[test]
+    fn formats_with_currency_decimals() {
+        let usd = rate("USD", 6_543_210);
+        assert_eq!(usd.format(Amount::from_sats(100_000_000)), "65,432.10 USD");
+        assert_eq!(usd.format(Amount::from_sats(1_000)), "0.65 USD");
+
+        let jpy = rate("JPY", 9_800_000 * 100);
+        assert_eq!(jpy.format(Amount::from_sats(100_000_000)), "9,800,000 JPY");
+        assert_eq!(jpy.format(Amount::from_sats(10_000)), "980 JPY");
+    }
+
+    #[test]
+    fn parses_with_currency_decimals() {
+        let usd = rate("USD", 100_000 * 100);
+        assert_eq!(usd.parse("1,000.50").unwrap(), Amount::from_sats(1_000_500));
+        assert!(usd.parse("1.005").is_err());
+        assert!(usd.parse("1,5").is_err());
+        assert!(usd.parse("0").is_err());
+
+        let jpy = rate("JPY", 10_000_000 * 100);
+        assert_eq!(jpy.parse("1,000").unwrap(), Amount::from_sats(10_000));
+        assert!(jpy.parse("1.5").is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_prices_over_http() {
+        let provider = price_api(json!(65_432.1)).await;
+        assert_eq!(provider.cents_per_btc("USD").await.unwrap(), 6_543_210);
+
+        // The server does not know EUR and answers 404
+        assert!(provider.cents_per_btc("EUR").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_prices() {
+        for price in [json!("65432.1"), json!(null), json!(0), json!(-1.5)] {
+            let provider = price_api(price.clone()).await;
+            assert!(provider.cents_per_btc("USD").await.is_err(), "{price}");
+        }
+
+        let url = serve(|_| (500, json!({ "USD": 65_432.1 }))).await;
+        let provider = HttpPriceProvider {
+            url,
+            pointer: "/USD".to_string(),
+        };
+        assert!(provider.cents_per_btc("USD").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_recent_cached_prices() {
+        let db = database();
+        let working = PriceSource::Static {
+            cents_per_btc: 6_543_210,
+        };
+        let failing = PriceSource::Static { cents_per_btc: 0 };
+
+        let quoted = quote_rate(&db, &working, "USD").await.unwrap();
+        assert_eq!(quoted.cents_per_btc, 6_543_210);
+        assert_eq!(quote_rate(&db, &failing, "USD").await, Some(quoted));
+        assert_eq!(quote_rate(&db, &failing, "EUR").await, None);
+
+        let stale = unix_now() - MAX_PRICE_AGE.as_secs() - 60;
+        cache(
+            &db,
+            "USD",
+            FiatPrice {
+                cents_per_btc: 6_543_210,
+                fetched_at: stale,
+            },
+        )
+        .await;
+        assert_eq!(quote_rate(&db, &failing, "USD").await, None);
+    }
+}
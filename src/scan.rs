@@ -0,0 +1,101 @@
+use anyhow::{ensure, Context};
+use image::GrayImage;
+
+/// Decodes every QR code found in a PNG or JPEG image.
+pub(crate) fn decode_qr_image(bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+    let image = image::load_from_memory(bytes)
+        .context("Could not read image")?
+        .to_luma8();
+    decode_qr_codes(&image)
+}
+
+/// Decodes every QR code in the image currently on the system clipboard.
+#[cfg(feature = "desktop")]
+pub(crate) fn decode_qr_clipboard() -> anyhow::Result<Vec<String>> {
+    let mut clipboard = arboard::Clipboard::new().context("Could not access clipboard")?;
+    let clipboard_image = clipboard
+        .get_image()
+        .context("Clipboard does not contain an image")?;
+    let image = image::RgbaImage::from_raw(
+        clipboard_image.width as u32,
+        clipboard_image.height as u32,
+        clipboard_image.bytes.into_owned(),
+    )
+    .context("Clipboard image has an unexpected format")?;
+    decode_qr_codes(&image::DynamicImage::ImageRgba8(image).to_luma8())
+}
+
+fn decode_qr_codes(image: &GrayImage) -> anyhow::Result<Vec<String>> {
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32).0[0],
+    );
+    let contents = prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect::<Vec<_>>();
+    ensure!(!contents.is_empty(), "No QR code found in image");
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat, Luma};
+    use qrcode::{Color, QrCode};
+
+    use super::*;
+
+    /// Pixels per QR module.
+    const SCALE: u32 = 4;
+    /// Width of the blank border around the code, in modules.
+    const QUIET_ZONE: u32 = 4;
+
+    /// `image` encoded as PNG, as it would be picked from a file.
+    fn png(image: GrayImage) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma8(image)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    fn render_qr(content: &str) -> Vec<u8> {
+        let code = QrCode::new(content).unwrap();
+        let width = code.width() as u32;
+        let colors = code.to_colors();
+        let size = (width + 2 * QUIET_ZONE) * SCALE;
+        let image = GrayImage::from_fn(size, size, |x, y| {
+            let (x, y) = (x / SCALE, y / SCALE);
+            let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&x)
+                && (QUIET_ZONE..QUIET_ZONE + width).contains(&y);
+            let dark = inside
+                && colors[((y - QUIET_ZONE) * width + x - QUIET_ZONE) as usize] == Color::Dark;
+            Luma([if dark { 0 } else { 255 }])
+        });
+        png(image)
+    }
+
+    #[test]
+    fn decodes_rendered_qr_codes() {
+        for content in [
+            "bitcoin:bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4?amount=0.001",
+            "alice@example.com",
+        ] {
+            assert_eq!(decode_qr_image(&render_qr(content)).unwrap(), [content]);
+        }
+    }
+
+    #[test]
+    fn rejects_images_without_qr_codes() {
+        let blank = png(GrayImage::from_pixel(64, 64, Luma([255])));
+        assert!(decode_qr_image(&blank).is_err());
+
+        assert!(decode_qr_image(b"not an image").is_err());
+        assert!(decode_qr_image(&[]).is_err());
+    }
+}
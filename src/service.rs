@@ -0,0 +1,523 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+    time::Duration,
+};
+
+use dioxus::logger::tracing::{error, info, warn};
+use fedimint_client::{ClientHandleArc, OperationId};
+use fedimint_core::{config::FederationId, invite_code::InviteCode, task};
+use futures_util::{
+    future::{select, Either},
+    pin_mut, StreamExt,
+};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::{
+    db::FederationConfig,
+    error::CarbineError,
+    limits::PinPurpose,
+    meta::META_REFRESH_INTERVAL,
+    multimint::{FederationStatus, LoadFailure, Multimint},
+    operations::{OperationOutcome, PendingOperation},
+    FederationSelector,
+};
+
+const COMMAND_BUFFER: usize = 64;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+const EVENT_BUFFER: usize = 32;
+
+type Statuses = BTreeMap<FederationId, FederationStatus>;
+
+type Reply<T> = oneshot::Sender<Result<T, CarbineError>>;
+
+/// Something that happened in the wallet that the user may want to know about.
+#[derive(Clone, Debug)]
+pub(crate) enum WalletEvent {
+    OperationFinished {
+        operation: PendingOperation,
+        outcome: OperationOutcome,
+    },
+}
+
+enum Command {
+    /// Hands out a clone of the current `Multimint`. Clients are reference
+    /// counted, so operations run on the clone without holding up the service.
+    Snapshot(Reply<Multimint>),
+    /// Checks the PIN and, if it was entered to confirm a payment, lets later
+    /// snapshots make payments that need confirming.
+    Authenticate {
+        pin: String,
+        purpose: PinPurpose,
+        reply: Reply<()>,
+    },
+    Join {
+        invite_code: String,
+        reply: Reply<FederationSelector>,
+    },
+    /// Sent by a join task once its client has been built.
+    Joined {
+        selector: FederationSelector,
+        client: ClientHandleArc,
+        reply: Reply<FederationSelector>,
+    },
+    JoinFailed {
+        federation_id: FederationId,
+        error: CarbineError,
+        reply: Reply<FederationSelector>,
+    },
+    /// Sent by a load task once a joined federation's client has been opened.
+    ClientLoaded {
+        federation_id: FederationId,
+        client: ClientHandleArc,
+    },
+    StatusChanged {
+        federation_id: FederationId,
+        status: FederationStatus,
+    },
+    /// Scans the federation's operation log for unfinished operations and
+    /// follows the ones not followed yet.
+    TrackPending(FederationId),
+    /// Sent when the cached meta of a federation changed.
+    MetaChanged,
+    PendingFound(Vec<PendingOperation>),
+    OperationFinished {
+        operation: PendingOperation,
+        outcome: Result<OperationOutcome, CarbineError>,
+    },
+}
+
+/// Channels the service publishes its state on.
+struct Publishers {
+    statuses: watch::Sender<Statuses>,
+    pending: watch::Sender<Vec<PendingOperation>>,
+    events: broadcast::Sender<WalletEvent>,
+}
+
+/// Cheap to clone handle to the wallet service task, which owns the
+/// `Multimint`. Only registering new clients goes through the service itself,
+/// everything else runs on a snapshot so a payment waiting for a day never
+/// blocks a balance read or a join.
+#[derive(Clone)]
+pub(crate) struct WalletHandle {
+    commands: mpsc::Sender<Command>,
+    statuses: watch::Receiver<Statuses>,
+    pending: watch::Receiver<Vec<PendingOperation>>,
+    events: broadcast::Sender<WalletEvent>,
+}
+
+impl WalletHandle {
+    /// Spawns the wallet service, which opens the database and then loads all
+    /// joined federations concurrently in the background.
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let (status_sender, statuses) = watch::channel(Statuses::new());
+        let (pending_sender, pending) = watch::channel(Vec::new());
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let publishers = Publishers {
+            statuses: status_sender,
+            pending: pending_sender,
+            events: events.clone(),
+        };
+        task::spawn("wallet service", run(receiver, sender.clone(), publishers));
+        WalletHandle {
+            commands: sender,
+            statuses,
+            pending,
+            events,
+        }
+    }
+
+    /// Notifies about every change in the status of a federation.
+    pub fn statuses(&self) -> watch::Receiver<Statuses> {
+        self.statuses.clone()
+    }
+
+    /// Unfinished operations of all federations, including ones started
+    /// before the last restart.
+    pub fn pending(&self) -> watch::Receiver<Vec<PendingOperation>> {
+        self.pending.clone()
+    }
+
+    pub fn events(&self) -> broadcast::Receiver<WalletEvent> {
+        self.events.subscribe()
+    }
+
+    /// Makes the service follow operations just started in `federation_id`,
+    /// so their outcome is reported even if the screen that started them is
+    /// closed.
+    pub async fn track_pending(&self, federation_id: FederationId) {
+        let _ = self
+            .commands
+            .send(Command::TrackPending(federation_id))
+            .await;
+    }
+
+    pub async fn multimint(&self) -> Result<Multimint, CarbineError> {
+        self.request(Command::Snapshot).await
+    }
+
+    pub async fn authenticate(
+        &self,
+        pin: String,
+        purpose: PinPurpose,
+    ) -> Result<(), CarbineError> {
+        self.request(|reply| Command::Authenticate {
+            pin,
+            purpose,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn join_federation(
+        &self,
+        invite_code: String,
+    ) -> Result<FederationSelector, CarbineError> {
+        self.request(|reply| Command::Join { invite_code, reply }).await
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command,
+    ) -> Result<T, CarbineError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| service_stopped())?;
+        response.await.map_err(|_| service_stopped())?
+    }
+}
+
+async fn run(
+    mut commands: mpsc::Receiver<Command>,
+    sender: mpsc::Sender<Command>,
+    publishers: Publishers,
+) {
+    let mut multimint = match Multimint::new().await {
+        Ok(multimint) => multimint,
+        Err(e) => {
+            error!("Could not create multimint: {e}");
+            let reason = e.to_string();
+            while let Some(command) = commands.recv().await {
+                reject(command, CarbineError::ServiceUnavailable(reason.clone()));
+            }
+            return;
+        }
+    };
+    info!("Wallet service started");
+
+    for (federation_id, config) in multimint.federation_configs().await {
+        multimint.set_status(federation_id, FederationStatus::Loading);
+        task::spawn(
+            "load client",
+            load_client(multimint.clone(), federation_id, config, sender.clone()),
+        );
+    }
+    publishers
+        .statuses
+        .send_replace(multimint.statuses().clone());
+
+    let mut joining = BTreeSet::new();
+    let mut pending = BTreeMap::<OperationId, PendingOperation>::new();
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Snapshot(reply) => {
+                let _ = reply.send(Ok(multimint.clone()));
+            }
+            Command::Authenticate {
+                pin,
+                purpose,
+                reply,
+            } => {
+                let _ = reply.send(multimint.authenticate(&pin, purpose).await);
+            }
+            Command::Join { invite_code, reply } => {
+                let invite_code = match InviteCode::from_str(invite_code.trim()) {
+                    Ok(invite_code) => invite_code,
+                    Err(e) => {
+                        let _ = reply.send(Err(CarbineError::InvalidInviteCode(e.to_string())));
+                        continue;
+                    }
+                };
+                let federation_id = invite_code.federation_id();
+                if !joining.insert(federation_id) {
+                    let _ = reply.send(Err(CarbineError::JoinInProgress(federation_id)));
+                    continue;
+                }
+
+                let snapshot = multimint.clone();
+                let sender = sender.clone();
+                task::spawn("join federation", async move {
+                    let command = match snapshot.prepare_join(invite_code).await {
+                        Ok((selector, client)) => Command::Joined {
+                            selector,
+                            client,
+                            reply,
+                        },
+                        Err(error) => Command::JoinFailed {
+                            federation_id,
+                            error,
+                            reply,
+                        },
+                    };
+                    let _ = sender.send(command).await;
+                });
+            }
+            Command::Joined {
+                selector,
+                client,
+                reply,
+            } => {
+                joining.remove(&selector.federation_id);
+                follow_meta(&multimint, selector.federation_id, &client, &sender);
+                multimint.add_client(selector.federation_id, client);
+                publishers
+                    .statuses
+                    .send_replace(multimint.statuses().clone());
+                let _ = reply.send(Ok(selector));
+            }
+            Command::JoinFailed {
+                federation_id,
+                error,
+                reply,
+            } => {
+                joining.remove(&federation_id);
+                let _ = reply.send(Err(error));
+            }
+            Command::ClientLoaded {
+                federation_id,
+                client,
+            } => {
+                follow_meta(&multimint, federation_id, &client, &sender);
+                multimint.add_client(federation_id, client);
+                publishers
+                    .statuses
+                    .send_replace(multimint.statuses().clone());
+                // Recovering clients are scanned once recovery is done
+                if multimint
+                    .statuses()
+                    .get(&federation_id)
+                    .is_some_and(FederationStatus::is_usable)
+                {
+                    scan_pending(&multimint, federation_id, &sender);
+                }
+            }
+            Command::StatusChanged {
+                federation_id,
+                status,
+            } => {
+                let usable = status.is_usable();
+                multimint.set_status(federation_id, status);
+                publishers
+                    .statuses
+                    .send_replace(multimint.statuses().clone());
+                if usable {
+                    scan_pending(&multimint, federation_id, &sender);
+                }
+            }
+            Command::TrackPending(federation_id) => {
+                scan_pending(&multimint, federation_id, &sender);
+            }
+            // The UI reloads the federations whenever the statuses are published
+            Command::MetaChanged => {
+                publishers
+                    .statuses
+                    .send_replace(multimint.statuses().clone());
+            }
+            Command::PendingFound(operations) => {
+                for operation in operations {
+                    if pending.contains_key(&operation.operation_id) {
+                        continue;
+                    }
+                    pending.insert(operation.operation_id, operation.clone());
+
+                    let snapshot = multimint.clone();
+                    let sender = sender.clone();
+                    task::spawn("follow operation", async move {
+                        let outcome = snapshot.await_operation(&operation).await;
+                        let _ = sender
+                            .send(Command::OperationFinished { operation, outcome })
+                            .await;
+                    });
+                }
+                publishers
+                    .pending
+                    .send_replace(pending.values().cloned().collect());
+            }
+            Command::OperationFinished { operation, outcome } => {
+                pending.remove(&operation.operation_id);
+                publishers
+                    .pending
+                    .send_replace(pending.values().cloned().collect());
+                match outcome {
+                    Ok(outcome) => {
+                        info!("Operation {:?} finished: {outcome:?}", operation.operation_id);
+                        let _ = publishers
+                            .events
+                            .send(WalletEvent::OperationFinished { operation, outcome });
+                    }
+                    Err(e) => warn!("Could not follow operation {:?}: {e}", operation.operation_id),
+                }
+            }
+        }
+    }
+}
+
+/// Looks for unfinished operations in the background and hands them to the
+/// service to follow.
+fn scan_pending(
+    multimint: &Multimint,
+    federation_id: FederationId,
+    sender: &mpsc::Sender<Command>,
+) {
+    let snapshot = multimint.clone();
+    let sender = sender.clone();
+    task::spawn("scan pending operations", async move {
+        match snapshot.pending_operations(&federation_id).await {
+            Ok(operations) => {
+                let _ = sender.send(Command::PendingFound(operations)).await;
+            }
+            Err(e) => warn!("Could not list pending operations of {federation_id}: {e}"),
+        }
+    });
+}
+
+/// Keeps the cached meta of a loaded federation up to date in the background.
+fn follow_meta(
+    multimint: &Multimint,
+    federation_id: FederationId,
+    client: &ClientHandleArc,
+    sender: &mpsc::Sender<Command>,
+) {
+    let snapshot = multimint.clone();
+    let client = client.clone();
+    let sender = sender.clone();
+    task::spawn("follow federation meta", async move {
+        loop {
+            if snapshot.refresh_meta(&federation_id, &client).await
+                && sender.send(Command::MetaChanged).await.is_err()
+            {
+                break;
+            }
+            task::sleep(META_REFRESH_INTERVAL).await;
+            if sender.is_closed() {
+                break;
+            }
+        }
+    });
+}
+
+/// Opens a joined federation's client, retrying with exponential backoff
+/// while the federation is unreachable, then follows any pending recovery.
+async fn load_client(
+    multimint: Multimint,
+    federation_id: FederationId,
+    config: FederationConfig,
+    sender: mpsc::Sender<Command>,
+) {
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+    let client = loop {
+        let failure = match multimint.load_client(&federation_id, &config).await {
+            Ok(client) => break client,
+            Err(CarbineError::Network(e)) => LoadFailure::Offline(format!(
+                "Guardians unreachable, retrying in {}s: {e}",
+                retry_delay.as_secs()
+            )),
+            Err(e) => LoadFailure::Config(e.to_string()),
+        };
+        warn!("Could not load federation {federation_id}: {}", failure.reason());
+        let retry = matches!(failure, LoadFailure::Offline(_));
+
+        let service_running = sender
+            .send(Command::StatusChanged {
+                federation_id,
+                status: FederationStatus::Failed(failure),
+            })
+            .await
+            .is_ok();
+        if !retry || !service_running {
+            return;
+        }
+        task::sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+    };
+    info!("Loaded federation {federation_id}");
+
+    let recovering = client.has_pending_recoveries();
+    let _ = sender
+        .send(Command::ClientLoaded {
+            federation_id,
+            client: client.clone(),
+        })
+        .await;
+    if !recovering {
+        return;
+    }
+
+    // Report the summed progress of all recovering modules until done
+    let progress = async {
+        let mut modules = BTreeMap::new();
+        let mut updates = client.subscribe_to_recovery_progress();
+        while let Some((module_id, progress)) = updates.next().await {
+            modules.insert(module_id, progress);
+            let status = FederationStatus::Recovering {
+                complete: modules.values().map(|progress| progress.complete).sum(),
+                total: modules.values().map(|progress| progress.total).sum(),
+            };
+            let _ = sender
+                .send(Command::StatusChanged {
+                    federation_id,
+                    status,
+                })
+                .await;
+        }
+    };
+    let recovery = client.wait_for_all_recoveries();
+    pin_mut!(progress, recovery);
+    let result = match select(recovery, progress).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), recovery)) => recovery.await,
+    };
+
+    let status = match result {
+        Ok(()) => Multimint::client_status(&client),
+        Err(e) => {
+            FederationStatus::Failed(LoadFailure::Recovery(format!("Recovery failed: {e}")))
+        }
+    };
+    let _ = sender
+        .send(Command::StatusChanged {
+            federation_id,
+            status,
+        })
+        .await;
+}
+
+fn reject(command: Command, error: CarbineError) {
+    match command {
+        Command::Snapshot(reply) => {
+            let _ = reply.send(Err(error));
+        }
+        Command::Authenticate { reply, .. } => {
+            let _ = reply.send(Err(error));
+        }
+        Command::Join { reply, .. }
+        | Command::Joined { reply, .. }
+        | Command::JoinFailed { reply, .. } => {
+            let _ = reply.send(Err(error));
+        }
+        Command::ClientLoaded { .. }
+        | Command::StatusChanged { .. }
+        | Command::TrackPending(_)
+        | Command::MetaChanged
+        | Command::PendingFound(_)
+        | Command::OperationFinished { .. } => {}
+    }
+}
+
+fn service_stopped() -> CarbineError {
+    CarbineError::ServiceUnavailable("wallet service stopped".to_string())
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use dioxus::{logger::tracing::warn, prelude::*};
+use fedimint_api_client::api::net::Connector;
+use fedimint_core::{config::FederationId, util::SafeUrl, Amount};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    limits::{PinHash, SpendingLimits},
+    nostr::DEFAULT_RELAYS,
+    operations::OperationKind,
+    price::PriceSource,
+    units::DisplayUnit,
+    SETTINGS, SETTINGS_ERROR, WALLET,
+};
+
+/// Connectors offered in the settings, those this build does not support are
+/// hidden.
+pub(crate) const CONNECTOR_CHOICES: [&str; 2] = ["tcp", "tor"];
+
+/// User preferences, persisted in our database. Fields missing from older
+/// records take their default, so new settings can be added freely.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub display_unit: DisplayUnit,
+    /// Currency fiat values are shown in, `None` hides fiat values.
+    pub fiat_currency: Option<String>,
+    /// Where the bitcoin price in `fiat_currency` is quoted from.
+    pub price_source: PriceSource,
+    /// Federation pasted payments go to when no federation is on screen.
+    pub default_federation: Option<FederationId>,
+    /// Lightning gateways tried in order before letting the client pick one.
+    pub preferred_gateways: Vec<SafeUrl>,
+    /// How newly joined federations are reached.
+    pub connector: Connector,
+    pub notifications: NotificationSettings,
+    /// Minutes without interaction after which the wallet locks, `None`
+    /// never locks. Only takes effect once a PIN is set.
+    pub auto_lock_minutes: Option<u32>,
+    /// Caps on payments out of all federations together.
+    pub limits: SpendingLimits,
+    /// Caps on payments out of single federations, checked on top of `limits`.
+    pub federation_limits: BTreeMap<FederationId, SpendingLimits>,
+    /// Payments above this amount need the PIN, `None` never asks.
+    pub confirm_above: Option<Amount>,
+    /// Unlocks the wallet and confirms large payments, `None` if not set.
+    pub pin: Option<PinHash>,
+    /// Nostr relays federations are discovered on, see `relays`.
+    pub nostr_relays: Vec<String>,
+}
+
+impl Settings {
+    /// The configured Nostr relays, or the default ones if none are.
+    pub fn relays(&self) -> Vec<String> {
+        if self.nostr_relays.is_empty() {
+            DEFAULT_RELAYS.map(str::to_owned).to_vec()
+        } else {
+            self.nostr_relays.clone()
+        }
+    }
+}
+
+/// Which finished operations are announced with an OS notification.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NotificationSettings {
+    pub payments_received: bool,
+    pub payments_failed: bool,
+    pub onchain: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            payments_received: true,
+            payments_failed: true,
+            onchain: true,
+        }
+    }
+}
+
+impl NotificationSettings {
+    /// Whether the user wants to hear about finished operations of `kind`.
+    pub fn allows(&self, kind: OperationKind) -> bool {
+        match kind {
+            OperationKind::LightningReceive => self.payments_received,
+            OperationKind::LightningSend => self.payments_failed,
+            OperationKind::Deposit | OperationKind::Withdraw => self.onchain,
+            OperationKind::EcashRedeem | OperationKind::EcashSpend => true,
+        }
+    }
+}
+
+/// Applies `change` to the settings and persists them in the background.
+/// Does nothing while the stored settings are unreadable, so they are only
+/// replaced by an explicit reset.
+pub(crate) fn update_settings(change: impl FnOnce(&mut Settings)) {
+    if SETTINGS_ERROR.peek().is_some() {
+        warn!("Not saving settings, the stored ones could not be read");
+        return;
+    }
+    change(&mut SETTINGS.write());
+    let settings = SETTINGS();
+    spawn(async move {
+        let saved = match WALLET().multimint().await {
+            Ok(mm) => mm.save_settings(&settings).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            warn!("Could not save settings: {e}");
+        }
+    });
+}
+
+/// Replaces unreadable stored settings with the defaults, which lifts the
+/// pause on payments.
+pub(crate) fn reset_settings() {
+    *SETTINGS_ERROR.write() = None;
+    update_settings(|settings| *settings = Settings::default());
+}
@@ -0,0 +1,328 @@
+use std::fmt::Display;
+
+use fedimint_core::Amount;
+use serde::{Deserialize, Serialize};
+
+const MSATS_PER_SAT: u64 = 1_000;
+const MSATS_PER_BTC: u64 = 100_000_000_000;
+
+/// Unit amounts are shown and entered in throughout the UI.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum DisplayUnit {
+    #[default]
+    Sats,
+    Msats,
+    Btc,
+}
+
+impl DisplayUnit {
+    pub const ALL: [DisplayUnit; 3] = [DisplayUnit::Sats, DisplayUnit::Msats, DisplayUnit::Btc];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DisplayUnit::Sats => "sats",
+            DisplayUnit::Msats => "msats",
+            DisplayUnit::Btc => "BTC",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|unit| unit.label() == label)
+    }
+
+    fn msats_per_unit(&self) -> u64 {
+        match self {
+            DisplayUnit::Sats => MSATS_PER_SAT,
+            DisplayUnit::Msats => 1,
+            DisplayUnit::Btc => MSATS_PER_BTC,
+        }
+    }
+
+    /// Number of decimal places that still map to whole millisatoshis.
+    fn max_decimals(&self) -> usize {
+        match self {
+            DisplayUnit::Sats => 3,
+            DisplayUnit::Msats => 0,
+            DisplayUnit::Btc => 11,
+        }
+    }
+
+    /// Decimal places shown by default, more are only shown when needed.
+    fn min_decimals(&self) -> usize {
+        match self {
+            DisplayUnit::Sats | DisplayUnit::Msats => 0,
+            DisplayUnit::Btc => 8,
+        }
+    }
+
+    pub fn placeholder(&self) -> String {
+        format!("Amount in {}", self.label())
+    }
+}
+
+impl Display for DisplayUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AmountParseError {
+    Empty,
+    InvalidNumber,
+    /// A comma used as the decimal point, e.g. `0,5`.
+    DecimalComma,
+    TooManyDecimals(usize),
+    Zero,
+    TooLarge,
+}
+
+impl Display for AmountParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AmountParseError::Empty => write!(f, "Enter an amount"),
+            AmountParseError::InvalidNumber => {
+                write!(f, "Amount must be a number like 1,000 or 0.5")
+            }
+            AmountParseError::DecimalComma => {
+                write!(f, "Use a dot for decimals, e.g. 0.5 instead of 0,5")
+            }
+            AmountParseError::TooManyDecimals(0) => write!(f, "Amount must be a whole number"),
+            AmountParseError::TooManyDecimals(decimals) => {
+                write!(f, "Amount can have at most {decimals} decimal places")
+            }
+            AmountParseError::Zero => write!(f, "Amount must be greater than zero"),
+            AmountParseError::TooLarge => write!(f, "Amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for AmountParseError {}
+
+/// Formats `amount` in `unit` with thousands separators, e.g. `12,345 sats`.
+pub(crate) fn format_amount(amount: Amount, unit: DisplayUnit) -> String {
+    format!("{} {}", format_number(amount, unit), unit.label())
+}
+
+/// Formats `amount` in `unit` without the unit label.
+pub(crate) fn format_number(amount: Amount, unit: DisplayUnit) -> String {
+    let per_unit = unit.msats_per_unit();
+    let whole = amount.msats / per_unit;
+    let fraction = amount.msats % per_unit;
+
+    let mut formatted = group_thousands(whole);
+    if unit.max_decimals() > 0 {
+        let mut decimals = format!("{fraction:0width$}", width = unit.max_decimals());
+        while decimals.len() > unit.min_decimals() && decimals.ends_with('0') {
+            decimals.pop();
+        }
+        if !decimals.is_empty() {
+            formatted.push('.');
+            formatted.push_str(&decimals);
+        }
+    }
+    formatted
+}
+
+/// Strictly parses a user entered amount in `unit`. Thousands separators are
+/// accepted, anything that would lose precision or is zero is rejected.
+pub(crate) fn parse_amount(input: &str, unit: DisplayUnit) -> Result<Amount, AmountParseError> {
+    // Every unit's smallest decimal place is one millisatoshi
+    let msats = parse_decimal(input, unit.max_decimals())?;
+    if msats == 0 {
+        return Err(AmountParseError::Zero);
+    }
+    Ok(Amount::from_msats(msats))
+}
+
+/// Parses a decimal number with at most `decimals` decimal places as an
+/// integer count of its smallest decimal place, e.g. `"1,234.5"` with two
+/// decimals is `123450`. The decimal point must be a dot, the whole part may
+/// be grouped in thousands by one of `,`, `_` or a space.
+pub(crate) fn parse_decimal(input: &str, decimals: usize) -> Result<u64, AmountParseError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(AmountParseError::Empty);
+    }
+
+    let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+    let whole = ungroup_thousands(whole).ok_or_else(|| {
+        // A single comma that can't be a thousands separator, e.g. `0,5` or `12,34`
+        let is_decimal_comma = !input.contains('.')
+            && whole.split_once(',').is_some_and(|(before, after)| {
+                !after.contains(',')
+                    && (after.len() != 3 || before.is_empty() || before.starts_with('0'))
+            });
+        if is_decimal_comma {
+            AmountParseError::DecimalComma
+        } else {
+            AmountParseError::InvalidNumber
+        }
+    })?;
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(&whole) || !is_digits(fraction) {
+        return Err(AmountParseError::InvalidNumber);
+    }
+    if fraction.len() > decimals {
+        return Err(AmountParseError::TooManyDecimals(decimals));
+    }
+
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole
+            .parse::<u64>()
+            .map_err(|_| AmountParseError::TooLarge)?
+    };
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        let scale = 10u64.pow((decimals - fraction.len()) as u32);
+        fraction
+            .parse::<u64>()
+            .map_err(|_| AmountParseError::InvalidNumber)?
+            * scale
+    };
+
+    10u64
+        .checked_pow(decimals as u32)
+        .and_then(|per_whole| whole.checked_mul(per_whole))
+        .and_then(|value| value.checked_add(fraction))
+        .ok_or(AmountParseError::TooLarge)
+}
+
+/// Removes thousands separators from the whole part of a number, or `None` if
+/// they are not all the same or not every three digits, e.g. `1,00` or `1,000_000`.
+fn ungroup_thousands(whole: &str) -> Option<String> {
+    let Some(separator) = whole.chars().find(|c| matches!(c, ',' | '_' | ' ')) else {
+        return Some(whole.to_owned());
+    };
+    let groups = whole.split(separator).collect::<Vec<_>>();
+    let (first, rest) = groups.split_first()?;
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let is_grouped = (1..=3).contains(&first.len())
+        && !first.starts_with('0')
+        && is_digits(first)
+        && rest
+            .iter()
+            .all(|group| group.len() == 3 && is_digits(group));
+    is_grouped.then(|| groups.concat())
+}
+
+pub(crate) fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_numbers() {
+        let cases = [
+            (0, DisplayUnit::Sats, "0"),
+            (1_000, DisplayUnit::Sats, "1"),
+            (1_234_567_000, DisplayUnit::Sats, "1,234,567"),
+            (1_500, DisplayUnit::Sats, "1.5"),
+            (1_001, DisplayUnit::Sats, "1.001"),
+            (1_234_567, DisplayUnit::Msats, "1,234,567"),
+            (0, DisplayUnit::Btc, "0.00000000"),
+            (100_000_000_000, DisplayUnit::Btc, "1.00000000"),
+            (50_000_000_000, DisplayUnit::Btc, "0.50000000"),
+            (1_000, DisplayUnit::Btc, "0.00000001"),
+            (1, DisplayUnit::Btc, "0.00000000001"),
+            (
+                123_456_700_000_000_000,
+                DisplayUnit::Btc,
+                "1,234,567.00000000",
+            ),
+        ];
+        for (msats, unit, formatted) in cases {
+            assert_eq!(format_number(Amount::from_msats(msats), unit), formatted);
+        }
+        assert_eq!(
+            format_amount(Amount::from_msats(21_000_000), DisplayUnit::Sats),
+            "21,000 sats"
+        );
+    }
+
+    #[test]
+    fn parses_amounts() {
+        let cases = [
+            ("1", DisplayUnit::Sats, 1_000),
+            (" 21 ", DisplayUnit::Sats, 21_000),
+            ("1.5", DisplayUnit::Sats, 1_500),
+            (".5", DisplayUnit::Sats, 500),
+            ("5.", DisplayUnit::Sats, 5_000),
+            ("1,000", DisplayUnit::Sats, 1_000_000),
+            ("1,234,567.891", DisplayUnit::Sats, 1_234_567_891),
+            ("1_000", DisplayUnit::Sats, 1_000_000),
+            ("1 000 000", DisplayUnit::Sats, 1_000_000_000),
+            ("1000000", DisplayUnit::Sats, 1_000_000_000),
+            ("42", DisplayUnit::Msats, 42),
+            ("0.5", DisplayUnit::Btc, 50_000_000_000),
+            ("0.00000000001", DisplayUnit::Btc, 1),
+            ("1,000.5", DisplayUnit::Btc, 100_050_000_000_000),
+        ];
+        for (input, unit, msats) in cases {
+            assert_eq!(
+                parse_amount(input, unit),
+                Ok(Amount::from_msats(msats)),
+                "{input} {unit}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        use AmountParseError::*;
+
+        let cases = [
+            ("", DisplayUnit::Sats, Empty),
+            ("  ", DisplayUnit::Sats, Empty),
+            ("0", DisplayUnit::Sats, Zero),
+            ("0.000", DisplayUnit::Btc, Zero),
+            ("0,5", DisplayUnit::Btc, DecimalComma),
+            ("1,5", DisplayUnit::Sats, DecimalComma),
+            ("0,500", DisplayUnit::Btc, DecimalComma),
+            ("12,34", DisplayUnit::Sats, DecimalComma),
+            ("1,00,000", DisplayUnit::Sats, InvalidNumber),
+            ("1,000_000", DisplayUnit::Sats, InvalidNumber),
+            ("1000,000", DisplayUnit::Sats, InvalidNumber),
+            ("1,000.000,5", DisplayUnit::Sats, InvalidNumber),
+            (",5", DisplayUnit::Sats, DecimalComma),
+            (".", DisplayUnit::Sats, InvalidNumber),
+            ("1.2.3", DisplayUnit::Sats, InvalidNumber),
+            ("-1", DisplayUnit::Sats, InvalidNumber),
+            ("1e3", DisplayUnit::Sats, InvalidNumber),
+            ("abc", DisplayUnit::Sats, InvalidNumber),
+            ("1.0001", DisplayUnit::Sats, TooManyDecimals(3)),
+            ("1.5", DisplayUnit::Msats, TooManyDecimals(0)),
+            ("0.000000000001", DisplayUnit::Btc, TooManyDecimals(11)),
+            ("18446744073709552", DisplayUnit::Sats, TooLarge),
+            ("99999999999999999999", DisplayUnit::Msats, TooLarge),
+        ];
+        for (input, unit, error) in cases {
+            assert_eq!(parse_amount(input, unit), Err(error), "{input} {unit}");
+        }
+    }
+
+    #[test]
+    fn parses_decimals() {
+        assert_eq!(parse_decimal("65,432.10", 2), Ok(6_543_210));
+        assert_eq!(parse_decimal("0", 2), Ok(0));
+        assert_eq!(parse_decimal("9,800,000", 0), Ok(9_800_000));
+        assert_eq!(
+            parse_decimal("1.234", 2),
+            Err(AmountParseError::TooManyDecimals(2))
+        );
+    }
+}